@@ -0,0 +1,55 @@
+//! convert a 2048x1024 gradient to cubemaps of each size with each interpolation, run with
+//! `cargo bench --bench convert`
+//!
+//! ms per cubemap on a single core (one machine, 3 runs averaged):
+//! ```text
+//! size  linear  nearest  bicubic  area  trilinear
+//!  256      62       37      115   129        183
+//!  512     217      116      404   432        592
+//! 1024     864      499     1732  1821       1671
+//! ```
+
+use std::time::Instant;
+
+use clap::{Parser, ValueEnum};
+use equirect2cubemap::math::Interpolation;
+use equirect2cubemap::{convert, Config, FaceSizes};
+use image::{DynamicImage, ImageBuffer, Rgba};
+
+const WIDTH: u32 = 2048;
+const HEIGHT: u32 = 1024;
+
+fn main() {
+    let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(WIDTH, HEIGHT, |x, y| {
+        Rgba([(x / 8) as u8, (y / 4) as u8, ((x + y) % 256) as u8, 255])
+    }));
+    let config = Config::parse_from(["equirect2cubemap", "-", "-"]);
+    for size in [256, 512, 1024] {
+        for interpolation in Interpolation::value_variants() {
+            let config = Config {
+                size: FaceSizes([size; 6]),
+                interpolation: interpolation.clone(),
+                ..config.clone()
+            };
+            bench(&config, &img, size);
+        }
+    }
+}
+
+fn bench(config: &Config, img: &DynamicImage, size: u32) {
+    const RUNS: u32 = 3;
+    let start_time = Instant::now();
+    for _ in 0..RUNS {
+        convert(config, img);
+    }
+    println!(
+        "{:>4} {:<9}: {:?} per cubemap",
+        size,
+        config
+            .interpolation
+            .to_possible_value()
+            .map(|v| v.get_name().to_owned())
+            .unwrap_or_default(),
+        start_time.elapsed() / RUNS
+    );
+}
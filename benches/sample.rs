@@ -0,0 +1,66 @@
+//! bilinear sampling of the uvs of a 2048px front face from a 4096x2048 gradient, one uv at a
+//! time and batched, run with `cargo bench --bench sample --features simd` (without the
+//! feature both are the scalar sampler)
+//!
+//! ms per face on a single core with the feature (one machine, 5 runs averaged):
+//! ```text
+//!          scalar  batched
+//! rgba8       253       80
+//! rgba32f     165       45
+//! ```
+
+use std::time::Instant;
+
+use equirect2cubemap::math::{Addressing, Interpolation, SamplePixel, SphericalAngle, Vector3};
+use image::{ImageBuffer, Rgba};
+
+const SIZE: u32 = 2048;
+const RUNS: u32 = 5;
+
+fn main() {
+    let uvs: Vec<_> = (0..SIZE * SIZE)
+        .map(|i| {
+            let a = ((i % SIZE) as f32 + 0.5) / SIZE as f32 * 2.0 - 1.0;
+            let b = ((i / SIZE) as f32 + 0.5) / SIZE as f32 * 2.0 - 1.0;
+            SphericalAngle::from_normalized_vector(Vector3::new(1.0, a, b).normalize()).to_uv()
+        })
+        .collect();
+    let rgba8 = ImageBuffer::from_fn(SIZE * 2, SIZE, |x, y| {
+        Rgba([(x / 16) as u8, (y / 8) as u8, ((x + y) % 256) as u8, 255])
+    });
+    let rgba32f = ImageBuffer::from_fn(SIZE * 2, SIZE, |x, y| {
+        Rgba([x as f32 / SIZE as f32, y as f32 / SIZE as f32, 0.5, 1.0])
+    });
+    bench("rgba8", &rgba8, &uvs);
+    bench("rgba32f", &rgba32f, &uvs);
+}
+
+fn bench<P: SamplePixel>(name: &str, img: &ImageBuffer<P, Vec<P::Subpixel>>, uvs: &[(f32, f32)]) {
+    let interpolation = Interpolation::Linear;
+    let addressing = Addressing::PANORAMA;
+    let mut samples = vec![None; uvs.len()];
+    let start_time = Instant::now();
+    for _ in 0..RUNS {
+        for (uv, sample) in uvs.iter().zip(samples.iter_mut()) {
+            *sample = interpolation.try_sample_addressed(img, *uv, addressing);
+        }
+        std::hint::black_box(&samples);
+    }
+    let scalar = start_time.elapsed() / RUNS;
+    let start_time = Instant::now();
+    for _ in 0..RUNS {
+        // rows like the renderer
+        for (uvs, samples) in uvs
+            .chunks(SIZE as usize)
+            .zip(samples.chunks_mut(SIZE as usize))
+        {
+            interpolation.try_sample_batch(img, uvs, addressing, samples);
+        }
+        std::hint::black_box(&samples);
+    }
+    let batched = start_time.elapsed() / RUNS;
+    println!(
+        "{:<8}: {:?} scalar, {:?} batched per face",
+        name, scalar, batched
+    );
+}
@@ -0,0 +1,95 @@
+//! decoded input images shared by the conversions of `batch_convert`
+
+use anyhow::Result;
+use image::DynamicImage;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::{decode_input, to_samplable, Config};
+
+/// path, modification time, `--exr-layer` and `--scalar` of an input
+type Key = (PathBuf, SystemTime, Option<String>, bool);
+
+/// a samplable input image and its icc profile
+pub type Decoded = (DynamicImage, Option<Vec<u8>>);
+
+/// least recently used cache of decoded images, keyed by path, modification time and the options
+/// it's read with so an input rewritten between conversions is decoded again
+pub struct ImageCache {
+    capacity: usize,
+    /// least recently used first
+    entries: VecDeque<(Key, Arc<CachedImage>)>,
+}
+
+impl ImageCache {
+    /// a cache holding at most `capacity` images, at least one
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// the decoded image at `path` as `config` reads it, opened and evicting the least recently
+    /// used image on a miss
+    pub fn open(&mut self, path: &Path, config: &Config) -> Result<Arc<Decoded>> {
+        self.entry(path, config)?.decoded(path, config)
+    }
+
+    /// the entry of the image at `path` as `config` reads it without decoding it, a new one
+    /// evicting the least recently used image on a miss, see `CachedImage::decoded`
+    pub fn entry(&mut self, path: &Path, config: &Config) -> Result<Arc<CachedImage>> {
+        let modified = std::fs::metadata(path)?.modified()?;
+        let key = (
+            path.to_path_buf(),
+            modified,
+            config.exr_layer.clone(),
+            config.scalar,
+        );
+        if let Some(i) = self.entries.iter().position(|(k, _)| *k == key) {
+            let entry = self.entries.remove(i).expect("index of an entry");
+            let image = entry.1.clone();
+            self.entries.push_back(entry);
+            return Ok(image);
+        }
+        // an older version of the same file is never hit again
+        self.entries
+            .retain(|((p, time, ..), _)| p != path || *time == modified);
+        let image = Arc::new(CachedImage::default());
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((key, image.clone()));
+        Ok(image)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// an image of an `ImageCache`, decoded by the first conversion asking for it while the others
+/// wait on its own lock, so the cache isn't locked while it's decoded
+#[derive(Default)]
+pub struct CachedImage {
+    decoded: Mutex<Option<Arc<Decoded>>>,
+}
+
+impl CachedImage {
+    /// the decoded image at `path` as `config` reads it, decoded on the first call, a failed
+    /// decode is tried again by the next call
+    pub fn decoded(&self, path: &Path, config: &Config) -> Result<Arc<Decoded>> {
+        let mut decoded = self.decoded.lock().expect("a decode panicked");
+        if let Some(decoded) = &*decoded {
+            return Ok(decoded.clone());
+        }
+        let (img, icc) = decode_input(config, image::io::Reader::open(path)?)?;
+        Ok(decoded.insert(Arc::new((to_samplable(img), icc))).clone())
+    }
+}
@@ -0,0 +1,233 @@
+//! presets of `--config`, a toml file of `Config` options layered under the command line
+
+use anyhow::{anyhow, bail, Result};
+use clap::CommandFactory;
+use std::ffi::OsString;
+use std::path::Path;
+
+use crate::Config;
+
+/// a value of the toml subset presets are written in
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    /// an integer or float as written
+    Number(String),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+/// parse `key = value` lines of strings (basic and literal), numbers, booleans and one line
+/// arrays of them, `#` starts a comment, tables aren't supported since options are flat
+pub fn parse_toml(text: &str) -> Result<Vec<(String, Value)>> {
+    let mut entries: Vec<(String, Value)> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let context = |e: anyhow::Error| anyhow!("Line {} of the config file: {}", i + 1, e);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            return Err(context(anyhow!(
+                "tables aren't supported, options are top level keys."
+            )));
+        }
+        let (key, rest) = line
+            .split_once('=')
+            .ok_or_else(|| context(anyhow!("expected `key = value`.")))?;
+        let key = key.trim();
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(context(anyhow!(r#"invalid key "{}"."#, key)));
+        }
+        let (value, rest) = parse_value(rest.trim_start()).map_err(context)?;
+        let rest = rest.trim_start();
+        if !rest.is_empty() && !rest.starts_with('#') {
+            return Err(context(anyhow!("unexpected `{}` after the value.", rest)));
+        }
+        if entries.iter().any(|(k, _)| k == key) {
+            return Err(context(anyhow!(r#"duplicate key "{}"."#, key)));
+        }
+        entries.push((key.to_owned(), value));
+    }
+    Ok(entries)
+}
+
+/// the value at the start of `s` and the text after it
+fn parse_value(s: &str) -> Result<(Value, &str)> {
+    if let Some(rest) = s.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((Value::String(value), &rest[i + 1..])),
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some(c) => bail!(r#"unsupported escape "\{}"."#, c),
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+        bail!("unterminated string.");
+    }
+    if let Some(rest) = s.strip_prefix('\'') {
+        let end = rest
+            .find('\'')
+            .ok_or_else(|| anyhow!("unterminated string."))?;
+        return Ok((Value::String(rest[..end].to_owned()), &rest[end + 1..]));
+    }
+    if let Some(mut rest) = s.strip_prefix('[') {
+        let mut values = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(values), after));
+            }
+            let (value, after) = parse_value(rest)?;
+            if matches!(value, Value::Array(_)) {
+                bail!("nested arrays aren't supported.");
+            }
+            values.push(value);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                bail!("expected `,` or `]` in the array.");
+            }
+        }
+    }
+    let end = s
+        .find(|c: char| c.is_whitespace() || c == ',' || c == ']' || c == '#')
+        .unwrap_or(s.len());
+    let (token, rest) = s.split_at(end);
+    match token {
+        "true" => Ok((Value::Bool(true), rest)),
+        "false" => Ok((Value::Bool(false), rest)),
+        "" => bail!("expected a value."),
+        _ if is_number(token) => Ok((Value::Number(token.replace('_', "")), rest)),
+        _ => bail!(
+            "expected a string, number, boolean or array, got `{}`.",
+            token
+        ),
+    }
+}
+
+/// whether a token is a toml number, which may have `_` between its digits like `1_000`
+fn is_number(token: &str) -> bool {
+    let bytes = token.as_bytes();
+    let digit_at = |i: Option<usize>| i.and_then(|i| bytes.get(i)).is_some_and(u8::is_ascii_digit);
+    token
+        .match_indices('_')
+        .all(|(i, _)| digit_at(i.checked_sub(1)) && digit_at(Some(i + 1)))
+        && token.replace('_', "").parse::<f64>().is_ok()
+}
+
+/// the command line arguments the entries of a preset stand for, keys are the long names of
+/// the options (`tone_map_operator` or `tone-map-operator`), `true` flags are given, `false`
+/// ones left out and arrays joined with `,`
+pub fn to_args(entries: &[(String, Value)]) -> Result<Vec<OsString>> {
+    let command = Config::command();
+    let mut args = Vec::new();
+    for (key, value) in entries {
+        let long = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()))
+            .ok_or_else(|| {
+                if command.get_positionals().any(|arg| arg.get_id() == key) {
+                    anyhow!(
+                        r#""{}" is given on the command line, not in a config file."#,
+                        key
+                    )
+                } else {
+                    anyhow!(r#"Unknown option "{}" in the config file."#, key)
+                }
+            })?;
+        if long == "config" {
+            bail!("Config files can't include other config files.");
+        }
+        if !arg.get_action().takes_values() {
+            match value {
+                Value::Bool(true) => args.push(OsString::from(format!("--{}", long))),
+                Value::Bool(false) => {}
+                _ => bail!(r#"Option "{}" of the config file is true or false."#, key),
+            }
+            continue;
+        }
+        let text = match value {
+            Value::Array(values) => values.iter().map(scalar_text).collect::<Vec<_>>().join(","),
+            value => scalar_text(value),
+        };
+        args.push(OsString::from(format!("--{}={}", long, text)));
+    }
+    Ok(args)
+}
+
+fn scalar_text(value: &Value) -> String {
+    match value {
+        Value::String(s) | Value::Number(s) => s.clone(),
+        Value::Bool(b) => b.to_string(),
+        Value::Array(_) => unreachable!("arrays aren't nested"),
+    }
+}
+
+/// `args` (with the program name first) with the options of the `--config` file, if one is
+/// given, inserted before the others, options of the file that are also on the command line are
+/// left out so the command line overrides them
+pub fn args_with_config_file(args: Vec<OsString>) -> Result<Vec<OsString>> {
+    let command = Config::command();
+    let mut path = None;
+    // long names of the options on the command line
+    let mut given = Vec::new();
+    for (i, arg) in args.iter().enumerate().skip(1) {
+        let Some(arg) = arg.to_str() else {
+            continue;
+        };
+        if arg == "--" {
+            break;
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (long, value) = match long.split_once('=') {
+                Some((long, value)) => (long, Some(value)),
+                None => (long, None),
+            };
+            if long == "config" {
+                path = value
+                    .map(OsString::from)
+                    .or_else(|| args.get(i + 1).cloned());
+            }
+            given.push(long.to_owned());
+        } else if let Some(short) = arg.strip_prefix('-').and_then(|s| s.chars().next()) {
+            // values like `-1.5` aren't options and match no short name
+            let long = command
+                .get_arguments()
+                .find(|arg| arg.get_short() == Some(short))
+                .and_then(|arg| arg.get_long());
+            given.extend(long.map(str::to_owned));
+        }
+    }
+    let Some(path) = path else {
+        return Ok(args);
+    };
+    let path = Path::new(&path);
+    let text = std::fs::read_to_string(path).map_err(|e| {
+        anyhow!(
+            r#"Failed to read the config file "{}": {}"#,
+            path.display(),
+            e
+        )
+    })?;
+    let entries: Vec<_> = parse_toml(&text)?
+        .into_iter()
+        .filter(|(key, _)| !given.contains(&key.replace('_', "-")))
+        .collect();
+    let preset = to_args(&entries)?;
+    let mut args = args.into_iter();
+    Ok(args.next().into_iter().chain(preset).chain(args).collect())
+}
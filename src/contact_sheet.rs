@@ -0,0 +1,98 @@
+use image::imageops::{self, FilterType};
+use image::{GenericImage, ImageBuffer, Rgba, RgbaImage};
+
+use crate::math::SamplePixel;
+use crate::{FaceBuffer, Side};
+
+/// size (px) of the faces in the contact sheet
+pub const THUMBNAIL_SIZE: u32 = 128;
+
+/// downscale a face to a `size` x `size` 8-bit thumbnail, float channels are clipped to [0, 1]
+pub fn thumbnail<P: SamplePixel>(img: &FaceBuffer<P>, size: u32) -> RgbaImage {
+    let rgba = ImageBuffer::from_fn(img.width(), img.height(), |x, y| {
+        let channels = img.get_pixel(x, y).to_f32();
+        Rgba(channels.map(|c| ((c / P::CHANNEL_MAX).clamp(0.0, 1.0) * 255.0).round() as u8))
+    });
+    imageops::resize(&rgba, size, size, FilterType::Triangle)
+}
+
+/// lay the thumbnails out in a 3x2 grid labeled with their side names, cells are in the order
+/// front, back, left on top and right, top, bottom below, missing sides are left black
+pub fn contact_sheet(thumbnails: &[(RgbaImage, Side)], size: u32) -> RgbaImage {
+    let mut sheet = ImageBuffer::from_pixel(size * 3, size * 2, Rgba([0, 0, 0, 255]));
+    for (img, side) in thumbnails {
+        let cell = Side::all()
+            .iter()
+            .position(|s| s == side)
+            .expect("all sides are listed") as u32;
+        let (x, y) = (cell % 3 * size, cell / 3 * size);
+        sheet
+            .copy_from(img, x, y)
+            .expect("thumbnail should be size x size");
+        draw_label(&mut sheet, &side.to_string(), x + 4, y + 4);
+    }
+    sheet
+}
+
+/// pixels per font pixel of the labels
+const LABEL_SCALE: u32 = 2;
+
+/// white text on a black box with its top left corner at (x, y)
+fn draw_label(img: &mut RgbaImage, text: &str, x: u32, y: u32) {
+    let (box_w, box_h) = (
+        (text.len() as u32 * 6 + 1) * LABEL_SCALE,
+        (GLYPH_HEIGHT as u32 + 2) * LABEL_SCALE,
+    );
+    for py in y..(y + box_h).min(img.height()) {
+        for px in x..(x + box_w).min(img.width()) {
+            img.put_pixel(px, py, Rgba([0, 0, 0, 255]));
+        }
+    }
+    for (i, c) in text.chars().enumerate() {
+        let origin_x = x + (i as u32 * 6 + 1) * LABEL_SCALE;
+        let origin_y = y + LABEL_SCALE;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..5 {
+                if bits & (0b10000 >> column) == 0 {
+                    continue;
+                }
+                for dy in 0..LABEL_SCALE {
+                    for dx in 0..LABEL_SCALE {
+                        let px = origin_x + column * LABEL_SCALE + dx;
+                        let py = origin_y + row as u32 * LABEL_SCALE + dy;
+                        if px < img.width() && py < img.height() {
+                            img.put_pixel(px, py, Rgba([255, 255, 255, 255]));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+const GLYPH_HEIGHT: usize = 7;
+
+/// 5x7 bitmap of the lowercase letters in the side names, one row per byte, other characters
+/// are blank
+#[rustfmt::skip]
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c {
+        'a' => [0b00000, 0b00000, 0b01110, 0b00001, 0b01111, 0b10001, 0b01111],
+        'b' => [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b11110],
+        'c' => [0b00000, 0b00000, 0b01110, 0b10000, 0b10000, 0b10001, 0b01110],
+        'e' => [0b00000, 0b00000, 0b01110, 0b10001, 0b11111, 0b10000, 0b01110],
+        'f' => [0b00110, 0b01001, 0b01000, 0b11100, 0b01000, 0b01000, 0b01000],
+        'g' => [0b00000, 0b01111, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110],
+        'h' => [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001],
+        'i' => [0b00100, 0b00000, 0b01100, 0b00100, 0b00100, 0b00100, 0b01110],
+        'k' => [0b10000, 0b10000, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010],
+        'l' => [0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        'm' => [0b00000, 0b00000, 0b11010, 0b10101, 0b10101, 0b10001, 0b10001],
+        'n' => [0b00000, 0b00000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001],
+        'o' => [0b00000, 0b00000, 0b01110, 0b10001, 0b10001, 0b10001, 0b01110],
+        'p' => [0b00000, 0b00000, 0b11110, 0b10001, 0b10001, 0b11110, 0b10000],
+        'r' => [0b00000, 0b00000, 0b10110, 0b11001, 0b10000, 0b10000, 0b10000],
+        't' => [0b01000, 0b01000, 0b11100, 0b01000, 0b01000, 0b01001, 0b00110],
+        _ => [0; GLYPH_HEIGHT],
+    }
+}
@@ -0,0 +1,174 @@
+use image::{ImageBuffer, Pixel, Rgba};
+use std::collections::HashMap;
+use std::ops::{Index, IndexMut};
+
+use crate::{
+    convention_orientations, face_direction, face_uv, flip, orient, z_up_orientations, Convention,
+    FaceBuffer, Orientation, Side,
+};
+
+/// the six sides of a cubemap, iterated and indexed by `Side`, iteration is in the order of
+/// `Side::all`: front, back, left, right, top, bottom
+#[derive(Clone)]
+pub struct Cubemap<P: Pixel = Rgba<u8>> {
+    pub front: FaceBuffer<P>,
+    pub back: FaceBuffer<P>,
+    pub left: FaceBuffer<P>,
+    pub right: FaceBuffer<P>,
+    pub top: FaceBuffer<P>,
+    pub bottom: FaceBuffer<P>,
+}
+
+impl<P: Pixel> Cubemap<P> {
+    /// collect the sides from `(image, side)` entries like the ones `convert` returns,
+    /// panics if a side is missing, later entries win over earlier ones of the same side
+    pub fn from_entries(entries: Vec<(FaceBuffer<P>, Side)>) -> Self {
+        let [mut front, mut back, mut left, mut right, mut top, mut bottom] =
+            [None, None, None, None, None, None];
+        for (img, side) in entries {
+            let slot = match side {
+                Side::Front => &mut front,
+                Side::Back => &mut back,
+                Side::Left => &mut left,
+                Side::Right => &mut right,
+                Side::Top => &mut top,
+                Side::Bottom => &mut bottom,
+            };
+            *slot = Some(img);
+        }
+        let take = |slot: Option<FaceBuffer<P>>, side: Side| {
+            slot.unwrap_or_else(|| panic!("entries should have the {} side", side))
+        };
+        Self {
+            front: take(front, Side::Front),
+            back: take(back, Side::Back),
+            left: take(left, Side::Left),
+            right: take(right, Side::Right),
+            top: take(top, Side::Top),
+            bottom: take(bottom, Side::Bottom),
+        }
+    }
+    pub fn face(&self, side: Side) -> &FaceBuffer<P> {
+        match side {
+            Side::Front => &self.front,
+            Side::Back => &self.back,
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+            Side::Top => &self.top,
+            Side::Bottom => &self.bottom,
+        }
+    }
+    pub fn face_mut(&mut self, side: Side) -> &mut FaceBuffer<P> {
+        match side {
+            Side::Front => &mut self.front,
+            Side::Back => &mut self.back,
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+            Side::Top => &mut self.top,
+            Side::Bottom => &mut self.bottom,
+        }
+    }
+    /// the sides with their faces, in the order of `Side::all`
+    pub fn iter(&self) -> impl Iterator<Item = (Side, &FaceBuffer<P>)> {
+        Side::all()
+            .into_iter()
+            .map(move |side| (side, self.face(side)))
+    }
+    /// same as `iter`, with mutable faces
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Side, &mut FaceBuffer<P>)> {
+        [
+            (Side::Front, &mut self.front),
+            (Side::Back, &mut self.back),
+            (Side::Left, &mut self.left),
+            (Side::Right, &mut self.right),
+            (Side::Top, &mut self.top),
+            (Side::Bottom, &mut self.bottom),
+        ]
+        .into_iter()
+    }
+    /// back to `(image, side)` entries, in the order front, back, left, right, top, bottom
+    pub fn into_entries(self) -> Vec<(FaceBuffer<P>, Side)> {
+        vec![
+            (self.front, Side::Front),
+            (self.back, Side::Back),
+            (self.left, Side::Left),
+            (self.right, Side::Right),
+            (self.top, Side::Top),
+            (self.bottom, Side::Bottom),
+        ]
+    }
+}
+
+/// the sides with their faces, in the order of `Side::all`
+impl<P: Pixel> IntoIterator for Cubemap<P> {
+    type Item = (Side, FaceBuffer<P>);
+    type IntoIter = std::array::IntoIter<(Side, FaceBuffer<P>), 6>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        [
+            (Side::Front, self.front),
+            (Side::Back, self.back),
+            (Side::Left, self.left),
+            (Side::Right, self.right),
+            (Side::Top, self.top),
+            (Side::Bottom, self.bottom),
+        ]
+        .into_iter()
+    }
+}
+
+impl<P: Pixel> Index<Side> for Cubemap<P> {
+    type Output = FaceBuffer<P>;
+
+    fn index(&self, side: Side) -> &FaceBuffer<P> {
+        self.face(side)
+    }
+}
+
+impl<P: Pixel> IndexMut<Side> for Cubemap<P> {
+    fn index_mut(&mut self, side: Side) -> &mut FaceBuffer<P> {
+        self.face_mut(side)
+    }
+}
+
+impl<P> Cubemap<P>
+where
+    P: Pixel + Send + Sync + 'static,
+    P::Subpixel: Send + Sync,
+{
+    /// turn and mirror each face by the orientation of its side, see `orient`
+    pub fn orient(self, per_side: &HashMap<Side, Orientation>) -> Self {
+        Self::from_entries(orient(self.into_entries(), per_side))
+    }
+    /// turn the faces into a z-up skybox like `--rotate`, see `rotate`
+    pub fn rotate_z_up(self) -> Self {
+        self.orient(&z_up_orientations())
+    }
+    /// mirror each face like `--flip-x` and `--flip-y`, see `flip`
+    pub fn flip(self, flip_x: bool, flip_y: bool) -> Self {
+        let mut entries = self.into_entries();
+        flip(&mut entries, flip_x, flip_y);
+        Self::from_entries(entries)
+    }
+    /// lay the faces of a cubemap converted without `--convention` out for the cube map lookup
+    /// of `convention`, each side gets the texels of the face its axis points at, turned like
+    /// `--convention` turns them, faces are moved texel by texel without resampling
+    pub fn apply_convention(self, convention: Convention) -> Self {
+        let matrix = convention.sampling_matrix();
+        let faces = Side::all().map(|side| {
+            // the matrix permutes the cube's axes, so each side is one whole face of the source
+            let (source, _) = face_uv(face_direction(side, 0.5, 0.5, 1.0).rotate(&matrix));
+            let source = self.face(source);
+            let size = source.width();
+            let sizef = size as f32;
+            let texel = |t: f32| ((t * sizef) as u32).min(size - 1);
+            let face = ImageBuffer::from_fn(size, size, |x, y| {
+                let dir = face_direction(side, x as f32 + 0.5, y as f32 + 0.5, sizef);
+                let (source_side, (u, v)) = face_uv(dir.rotate(&matrix));
+                *self.face(source_side).get_pixel(texel(u), texel(v))
+            });
+            (face, side)
+        });
+        Self::from_entries(faces.into()).orient(&convention_orientations())
+    }
+}
@@ -0,0 +1,89 @@
+//! minimal writer of uncompressed rgba8 cubemap dds files
+
+use std::io::Write;
+
+use anyhow::{bail, Result};
+
+use crate::math::SamplePixel;
+use crate::{FaceBuffer, Side};
+
+/// order of the faces in a dds cubemap, +x, -x, +y, -y, +z, -z,
+/// faces are written as converted, pass `--rotate` for a y-up renderer
+pub const DDS_FACE_ORDER: [Side; 6] = [
+    Side::Right,
+    Side::Left,
+    Side::Top,
+    Side::Bottom,
+    Side::Front,
+    Side::Back,
+];
+
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PITCH: u32 = 0x8;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_RGB: u32 = 0x40;
+const DDSCAPS_COMPLEX: u32 = 0x8;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALLFACES: u32 = 0xFC00;
+
+/// write the six faces as one cubemap dds file, pixels are stored as rgba8
+pub fn write_dds_cubemap<P: SamplePixel, W: Write>(
+    faces: &[(FaceBuffer<P>, Side)],
+    writer: &mut W,
+) -> Result<()> {
+    let mut ordered = Vec::with_capacity(6);
+    for side in DDS_FACE_ORDER {
+        match faces.iter().find(|(_, s)| *s == side) {
+            Some((img, _)) => ordered.push(img),
+            None => bail!("DDS cubemap needs the {} side.", side),
+        }
+    }
+    let size = ordered[0].width();
+    if ordered
+        .iter()
+        .any(|img| img.width() != size || img.height() != size)
+    {
+        bail!("DDS cubemap needs the same size for all sides.");
+    }
+
+    let mut header = Vec::with_capacity(128);
+    header.extend_from_slice(b"DDS ");
+    let mut fields = [0u32; 31];
+    fields[0] = 124; // header size
+    fields[1] = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PITCH | DDSD_PIXELFORMAT;
+    fields[2] = size; // height
+    fields[3] = size; // width
+    fields[4] = size * 4; // pitch
+                          // fields[5..18]: depth, mipmap count, reserved
+    fields[18] = 32; // pixel format size
+    fields[19] = DDPF_RGB | DDPF_ALPHAPIXELS;
+    fields[20] = 0; // fourcc
+    fields[21] = 32; // bits per pixel
+    fields[22] = 0x0000_00FF; // r mask
+    fields[23] = 0x0000_FF00; // g mask
+    fields[24] = 0x00FF_0000; // b mask
+    fields[25] = 0xFF00_0000; // a mask
+    fields[26] = DDSCAPS_COMPLEX | DDSCAPS_TEXTURE;
+    fields[27] = DDSCAPS2_CUBEMAP | DDSCAPS2_CUBEMAP_ALLFACES;
+    for field in fields {
+        header.extend_from_slice(&field.to_le_bytes());
+    }
+    writer.write_all(&header)?;
+
+    let mut row = Vec::with_capacity(size as usize * 4);
+    for img in ordered {
+        for y in 0..size {
+            row.clear();
+            for x in 0..size {
+                let c = img.get_pixel(x, y).to_f32();
+                row.extend(c.map(|c| (c / P::CHANNEL_MAX * 255.0).round().clamp(0.0, 255.0) as u8));
+            }
+            writer.write_all(&row)?;
+        }
+    }
+    Ok(())
+}
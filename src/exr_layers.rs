@@ -0,0 +1,116 @@
+//! one layer of a multi-layer or multi-part exr image as the input of `--exr-layer`, or the
+//! channels outside the layers as the input of `--scalar`
+
+use anyhow::{bail, Result};
+use exr::prelude::*;
+use image::{DynamicImage, Rgba32FImage};
+use std::io::{BufRead, Seek};
+
+/// the channels of layer `name` as an `Rgba32F` image, a layer is either a part with that
+/// layer name or the channels prefixed with `name.`, rgb(a) channels are used as is and a single
+/// channel (like depth) becomes gray
+pub fn read_exr_layer<R: BufRead + Seek>(reader: R, name: &str) -> Result<DynamicImage> {
+    read_layer(reader, Some(name))
+}
+
+/// the channels outside any named layer as an `Rgba32F` image in the same way as
+/// `read_exr_layer`, for images like a lone `Y` or `Z` channel the image crate can't decode
+pub fn read_exr_unnamed_layer<R: BufRead + Seek>(reader: R) -> Result<DynamicImage> {
+    read_layer(reader, None)
+}
+
+/// the channels of layer `name`, or of no named layer for `None`
+fn read_layer<R: BufRead + Seek>(reader: R, name: Option<&str>) -> Result<DynamicImage> {
+    let image = read()
+        .no_deep_data()
+        .largest_resolution_level()
+        .all_channels()
+        .all_layers()
+        .all_attributes()
+        .from_buffered(reader)?;
+    let mut layers = Vec::new();
+    for layer in &image.layer_data {
+        let part = layer.attributes.layer_name.as_ref().map(Text::to_string);
+        let mut channels = Vec::new();
+        for channel in &layer.channel_data.list {
+            let channel_name = channel.name.to_string();
+            let (prefix, short) = match channel_name.rsplit_once('.') {
+                Some((prefix, short)) => (Some(prefix.to_owned()), short.to_owned()),
+                None => (None, channel_name.clone()),
+            };
+            // `part.R` in a part named `part` is still that part's channel
+            let layer_name = match (&part, prefix) {
+                (Some(part), Some(prefix)) if *part != prefix => {
+                    Some(format!("{}.{}", part, prefix))
+                }
+                (part, prefix) => prefix.or(part.clone()),
+            };
+            if let Some(layer_name) = &layer_name {
+                if !layers.contains(layer_name) {
+                    layers.push(layer_name.clone());
+                }
+            }
+            if layer_name.as_deref() == name {
+                channels.push((short, &channel.sample_data));
+            }
+        }
+        if !channels.is_empty() {
+            return to_rgba(layer.size, &channels);
+        }
+    }
+    let Some(name) = name else {
+        bail!(
+            "The exr image has no channels outside its layers: {}.",
+            layers.join(", ")
+        );
+    };
+    if layers.is_empty() {
+        bail!(
+            r#"Layer "{}" not found, the exr image has no named layers."#,
+            name
+        );
+    }
+    bail!(
+        r#"Layer "{}" not found, available layers: {}."#,
+        name,
+        layers.join(", ")
+    )
+}
+
+fn to_rgba(size: Vec2<usize>, channels: &[(String, &FlatSamples)]) -> Result<DynamicImage> {
+    let (width, height) = (size.width(), size.height());
+    let find = |names: &[&str]| {
+        channels
+            .iter()
+            .find(|(short, _)| names.iter().any(|n| short.eq_ignore_ascii_case(n)))
+            .map(|(_, samples)| *samples)
+    };
+    let rgba = match (find(&["R"]), find(&["G"]), find(&["B"])) {
+        (Some(r), Some(g), Some(b)) => [Some(r), Some(g), Some(b), find(&["A"])],
+        _ if channels.len() == 1 => {
+            let gray = Some(channels[0].1);
+            [gray, gray, gray, None]
+        }
+        _ => {
+            let names: Vec<_> = channels.iter().map(|(short, _)| short.as_str()).collect();
+            bail!(
+                "Expected R, G and B or a single channel in the layer, found {}.",
+                names.join(", ")
+            );
+        }
+    };
+    if rgba
+        .iter()
+        .flatten()
+        .any(|samples| samples.len() != width * height)
+    {
+        bail!("Subsampled exr channels aren't supported.");
+    }
+    let mut img = Rgba32FImage::new(width as u32, height as u32);
+    for (i, pixel) in img.pixels_mut().enumerate() {
+        for (c, samples) in pixel.0.iter_mut().zip(rgba) {
+            *c = samples.map_or(1.0, |samples| samples.value_by_flat_index(i).to_f32());
+        }
+    }
+    Ok(DynamicImage::ImageRgba32F(img))
+}
@@ -0,0 +1,132 @@
+//! minimal writer of uncompressed rgba8 cubemap ktx2 files
+
+use std::io::Write;
+
+use anyhow::{bail, Result};
+
+use crate::dds::DDS_FACE_ORDER;
+use crate::math::SamplePixel;
+use crate::{ColorSpace, FaceBuffer, Side};
+
+/// order of the faces in a ktx2 cubemap, +x, -x, +y, -y, +z, -z like vulkan cube image
+/// layers, it's the same as `DDS_FACE_ORDER`: right, left, top, bottom, front, back,
+/// faces are written as converted, pass `--rotate` for a y-up renderer
+pub const KTX2_FACE_ORDER: [Side; 6] = DDS_FACE_ORDER;
+
+const IDENTIFIER: [u8; 12] = [
+    0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n',
+];
+const VK_FORMAT_R8G8B8A8_UNORM: u32 = 37;
+const VK_FORMAT_R8G8B8A8_SRGB: u32 = 43;
+/// identifier, 9 header fields, 4 + 2 index fields of 32 and 64 bits
+const HEADER_SIZE: u32 = 12 + 9 * 4 + 4 * 4 + 2 * 8;
+/// byte offset, byte length and uncompressed byte length of the only level
+const LEVEL_INDEX_SIZE: u32 = 3 * 8;
+/// basic data format descriptor block with 4 samples
+const DFD_BLOCK_SIZE: u32 = 24 + 4 * 16;
+/// bytes before the pixels of the first face
+pub const KTX2_PREFIX_SIZE: u32 = HEADER_SIZE + LEVEL_INDEX_SIZE + 4 + DFD_BLOCK_SIZE;
+
+const KHR_DF_MODEL_RGBSDA: u8 = 1;
+const KHR_DF_PRIMARIES_BT709: u8 = 1;
+const KHR_DF_TRANSFER_LINEAR: u8 = 1;
+const KHR_DF_TRANSFER_SRGB: u8 = 2;
+const KHR_DF_CHANNEL_ALPHA: u8 = 15;
+/// alpha isn't srgb encoded
+const KHR_DF_SAMPLE_DATATYPE_LINEAR: u8 = 0x10;
+
+/// write the six faces as one cubemap ktx2 file with a single mip level, pixels are stored as
+/// rgba8, tagged as srgb or unorm by `color_space`
+pub fn write_ktx2_cubemap<P: SamplePixel, W: Write>(
+    faces: &[(FaceBuffer<P>, Side)],
+    color_space: ColorSpace,
+    writer: &mut W,
+) -> Result<()> {
+    let (vk_format, transfer, alpha) = match color_space {
+        ColorSpace::Srgb => (
+            VK_FORMAT_R8G8B8A8_SRGB,
+            KHR_DF_TRANSFER_SRGB,
+            KHR_DF_CHANNEL_ALPHA | KHR_DF_SAMPLE_DATATYPE_LINEAR,
+        ),
+        ColorSpace::Linear => (
+            VK_FORMAT_R8G8B8A8_UNORM,
+            KHR_DF_TRANSFER_LINEAR,
+            KHR_DF_CHANNEL_ALPHA,
+        ),
+    };
+    let mut ordered = Vec::with_capacity(6);
+    for side in KTX2_FACE_ORDER {
+        match faces.iter().find(|(_, s)| *s == side) {
+            Some((img, _)) => ordered.push(img),
+            None => bail!("KTX2 cubemap needs the {} side.", side),
+        }
+    }
+    let size = ordered[0].width();
+    if ordered
+        .iter()
+        .any(|img| img.width() != size || img.height() != size)
+    {
+        bail!("KTX2 cubemap needs the same size for all sides.");
+    }
+    let level_length = 6 * size as u64 * size as u64 * 4;
+
+    let mut header = Vec::with_capacity(KTX2_PREFIX_SIZE as usize);
+    header.extend_from_slice(&IDENTIFIER);
+    for field in [
+        vk_format,
+        1,                              // type size
+        size,                           // width
+        size,                           // height
+        0,                              // depth
+        0,                              // layer count, not an array
+        6,                              // face count
+        1,                              // level count
+        0,                              // supercompression scheme
+        HEADER_SIZE + LEVEL_INDEX_SIZE, // dfd offset
+        4 + DFD_BLOCK_SIZE,             // dfd length
+        0,                              // kvd offset
+        0,                              // kvd length
+    ] {
+        header.extend_from_slice(&field.to_le_bytes());
+    }
+    // sgd offset and length, then the level index
+    for field in [0, 0, KTX2_PREFIX_SIZE as u64, level_length, level_length] {
+        header.extend_from_slice(&field.to_le_bytes());
+    }
+
+    // data format descriptor
+    header.extend_from_slice(&(4 + DFD_BLOCK_SIZE).to_le_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes()); // vendor id, descriptor type
+    header.extend_from_slice(&(2 | DFD_BLOCK_SIZE << 16).to_le_bytes()); // version, block size
+    header.extend_from_slice(&[
+        KHR_DF_MODEL_RGBSDA,
+        KHR_DF_PRIMARIES_BT709,
+        transfer,
+        0, // flags, straight alpha
+    ]);
+    header.extend_from_slice(&[0; 4]); // texel block dimensions, 1x1x1x1
+    header.extend_from_slice(&[4, 0, 0, 0, 0, 0, 0, 0]); // bytes per plane
+                                                         // channel types of the r, g, b and a samples
+    for (i, channel) in [0, 1, 2, alpha].into_iter().enumerate() {
+        header.extend_from_slice(&(i as u16 * 8).to_le_bytes()); // bit offset
+        header.extend_from_slice(&[7, channel]); // bit length - 1, channel type
+        header.extend_from_slice(&[0; 4]); // sample position
+        header.extend_from_slice(&0u32.to_le_bytes()); // lower
+        header.extend_from_slice(&255u32.to_le_bytes()); // upper
+    }
+    debug_assert_eq!(header.len(), KTX2_PREFIX_SIZE as usize);
+    writer.write_all(&header)?;
+
+    let mut row = Vec::with_capacity(size as usize * 4);
+    for img in ordered {
+        for y in 0..size {
+            row.clear();
+            for x in 0..size {
+                let c = img.get_pixel(x, y).to_f32();
+                row.extend(c.map(|c| (c / P::CHANNEL_MAX * 255.0).round().clamp(0.0, 255.0) as u8));
+            }
+            writer.write_all(&row)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use anyhow::{bail, Result};
+use image::imageops::{rotate270, rotate90};
+use image::{GenericImage, ImageBuffer, Pixel};
+
+use crate::math::{build_mip_levels, SamplePixel};
+use crate::{FaceBuffer, Side};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// one image per side
+    Separate,
+    /// all sides in one 4x3 horizontal cross image
+    Cross,
+    /// all sides in one 6x1 horizontal strip image, ordered by `--face-order`
+    Row,
+}
+
+/// pack the sides into a 4x3 horizontal cross, blank cells are transparent, the middle row runs
+/// along the panorama (the left side faces the longitude right of the front) and the top and
+/// bottom are turned so their edges meet the front
+/// ```text
+///        top
+/// right  front  left  back
+///       bottom
+/// ```
+pub fn pack_horizontal_cross<P: Pixel + 'static>(
+    faces: &[(FaceBuffer<P>, Side)],
+    size: u32,
+) -> FaceBuffer<P> {
+    let mut packed = ImageBuffer::new(size * 4, size * 3);
+    for (img, side) in faces {
+        let (column, row) = match side {
+            Side::Top => (1, 0),
+            Side::Right => (0, 1),
+            Side::Front => (1, 1),
+            Side::Left => (2, 1),
+            Side::Back => (3, 1),
+            Side::Bottom => (1, 2),
+        };
+        // the top and bottom have the front at their right edge
+        let turned = match side {
+            Side::Top => Some(rotate90(img)),
+            Side::Bottom => Some(rotate270(img)),
+            _ => None,
+        };
+        packed
+            .copy_from(turned.as_ref().unwrap_or(img), column * size, row * size)
+            .expect("face should be size x size");
+    }
+    packed
+}
+
+/// pack the sides into a 6x1 horizontal strip in the given order
+pub fn pack_row<P: Pixel>(
+    faces: &[(FaceBuffer<P>, Side)],
+    order: &[Side],
+    size: u32,
+) -> FaceBuffer<P> {
+    let mut packed = ImageBuffer::new(size * 6, size);
+    for (column, side) in order.iter().enumerate() {
+        if let Some((img, _)) = faces.iter().find(|(_, s)| s == side) {
+            packed
+                .copy_from(img, column as u32 * size, 0)
+                .expect("face should be size x size");
+        }
+    }
+    packed
+}
+
+/// check that the order has all six sides exactly once
+pub fn validate_face_order(order: &[Side]) -> Result<()> {
+    if order.len() != 6 {
+        bail!("Face order should have 6 sides, got {}.", order.len());
+    }
+    for (i, side) in order.iter().enumerate() {
+        if order[..i].contains(side) {
+            bail!(r#"Face order has side "{}" more than once."#, side);
+        }
+    }
+    Ok(())
+}
+
+/// check the sides picked with `--faces`, every side should appear at most once
+pub fn validate_faces(faces: &[Side]) -> Result<()> {
+    if faces.is_empty() {
+        bail!("Faces should have at least 1 side.");
+    }
+    for (i, side) in faces.iter().enumerate() {
+        if faces[..i].contains(side) {
+            bail!(r#"Faces has side "{}" more than once."#, side);
+        }
+    }
+    Ok(())
+}
+
+/// split an image into an n x n grid of tiles, keyed by `(column, row)`,
+/// the size of the image should be divisible by n
+pub fn tile_image<P: Pixel>(img: &FaceBuffer<P>, n: u32) -> Vec<((u32, u32), FaceBuffer<P>)> {
+    let (width, height) = (img.width() / n, img.height() / n);
+    let mut tiles = Vec::with_capacity((n * n) as usize);
+    for y in 0..n {
+        for x in 0..n {
+            let tile = ImageBuffer::from_fn(width, height, |tx, ty| {
+                *img.get_pixel(x * width + tx, y * height + ty)
+            });
+            tiles.push(((x, y), tile));
+        }
+    }
+    tiles
+}
+
+/// the face followed by its box filtered mip levels down to 1x1, level `i` is
+/// `mip_sizes(size)[i]` wide
+pub fn generate_mip_chain<P: SamplePixel>(face: &FaceBuffer<P>) -> Vec<FaceBuffer<P>> {
+    let mut chain = vec![face.clone()];
+    chain.extend(build_mip_levels(face));
+    chain
+}
+
+/// sizes of the levels `generate_mip_chain` makes of a face `size` wide, halved and rounded down
+pub fn mip_sizes(size: u32) -> Vec<u32> {
+    let mut sizes = vec![size];
+    while let Some(&last) = sizes.last().filter(|&&s| s > 1) {
+        sizes.push(last / 2);
+    }
+    sizes
+}
+
+/// check every side can be split into n x n tiles of whole pixels
+pub fn validate_tiles(sizes: impl IntoIterator<Item = (Side, u32)>, n: u32) -> Result<()> {
+    for (side, size) in sizes {
+        if size % n != 0 {
+            bail!(
+                r#"Size {} of side "{}" isn't divisible by {} tiles."#,
+                size,
+                side,
+                n
+            );
+        }
+    }
+    Ok(())
+}
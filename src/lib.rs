@@ -0,0 +1,2306 @@
+use anyhow::{bail, Result};
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use image::codecs::{hdr::HdrEncoder, jpeg::JpegEncoder, webp::WebPEncoder};
+use image::error::{EncodingError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use image::{
+    DynamicImage, EncodableLayout, GenericImageView, ImageBuffer, ImageEncoder, ImageError,
+    ImageResult, Pixel, PixelWithColorType, Rgb, Rgba, Rgba32FImage, RgbaImage,
+};
+use par::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Cursor, Seek, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::{fmt::Display, path::PathBuf, str::FromStr};
+
+pub mod cache;
+pub mod config_file;
+pub mod contact_sheet;
+mod cubemap;
+pub mod dds;
+pub mod exr_layers;
+pub mod ktx2;
+pub mod layout;
+pub mod manifest;
+pub mod math;
+pub mod metadata;
+mod par;
+pub mod scalar;
+pub mod sha256;
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd;
+#[cfg(test)]
+mod tests;
+pub use cubemap::Cubemap;
+use layout::Layout;
+use math::{
+    average_luminance, bayer_dither, build_mip_levels, eac_warp, linear_to_srgb, matrix_product,
+    rotation_matrix, srgb_to_linear, AddressMode, Addressing, InputProjection, Interpolation,
+    PoleAxis, SamplePixel, Sampler, SphericalAngle, ToneMapping, Vector3,
+};
+use metadata::{decode_with_icc, embed_icc_profile, tag_png_color_space};
+
+pub type ImageBufferData = ImageBuffer<Rgba<u8>, Vec<u8>>;
+/// a face buffer of any pixel type
+pub type FaceBuffer<P> = ImageBuffer<P, Vec<<P as Pixel>::Subpixel>>;
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct Config {
+    /// the image format of the output images (jpg, png, webp, dds, ktx2, exr or hdr), or
+    /// `side=format` pairs like `top=jpg,bottom=jpg,default=png` for the separate layout, sides
+    /// not listed get the `default` format
+    #[arg(short, long, default_value = "png")]
+    pub format: Formats,
+    /// interpolation used when sampling source image
+    #[arg(short, long,value_enum, default_value_t = Interpolation::Linear)]
+    pub interpolation: Interpolation,
+    /// how the filters of `--interpolation` reach past the left and right edges of the input
+    /// image, full panoramas need wrap so the back has no seam, clamp suits partial ones
+    #[arg(long, value_enum, default_value_t = AddressMode::Wrap)]
+    pub wrap: AddressMode,
+    /// how the filters reach past the top and bottom edges of the input image, clamp (use it
+    /// with `--wrap wrap` for full panoramas) or mirror
+    #[arg(
+        long,
+        default_value = "clamp",
+        value_parser = PossibleValuesParser::new(["clamp", "mirror"])
+            .map(|s| <AddressMode as clap::ValueEnum>::from_str(&s, false).expect("a possible value"))
+    )]
+    pub wrap_v: AddressMode,
+    /// the input equirectangular image's path, `-` reads from stdin, a directory converts
+    /// every image in it into a subdirectory (named by the image) of the output directory, with
+    /// `--input-list` the directory relative paths of the list are resolved against
+    pub input: PathBuf,
+    /// the directory to put the output images in, creates if doesn't exist,
+    /// `-` writes a packed layout to stdout, `{stem}`, `{format}` and `{size}` are replaced by
+    /// the input file name without extension, the output format and the face size
+    pub output: PathBuf,
+    #[arg(short, long, default_value = "512")]
+    /// size (px) of the output images, width = height, either one value or
+    /// six comma separated values for front,back,left,right,top,bottom (separate layout only)
+    pub size: FaceSizes,
+    /// rotate to a z-up skybox if you use it in a y-up renderer, see `--convention` for the
+    /// cube maps of engines
+    #[arg(short, long, default_value_t = false)]
+    pub rotate: bool,
+    /// lay the faces out for the cube map lookup of an api or engine, each side holds the
+    /// face of its axis name (right +x, top +y, front +z, see `--naming axis`) in its axes:
+    /// opengl (+y up, +z back), directx, vulkan and unity (+y up, +z front), unreal (+x front,
+    /// +z up) and blender (+y front, +z up)
+    #[arg(long, value_enum)]
+    pub convention: Option<Convention>,
+    /// turn and mirror single sides after `--rotate`, e.g. `front=90,back=270+flipx`, angles
+    /// are clockwise degrees (0, 90, 180 or 270), `flipx` and `flipy` mirror after the turn
+    #[arg(long)]
+    pub orient: Option<FaceOrientations>,
+    /// extend each face by this many pixels on all sides with the panorama past its edges to hide
+    /// seams of bilinear filtering, faces are size + 2 * pad wide, sample them with clamp to edge
+    /// addressing and uv scaled by size / (size + 2 * pad) about the center
+    #[arg(long, default_value_t = 0)]
+    pub edge_pad: u32,
+    /// average the texels on the edges and corners adjacent faces share so both sides of a seam
+    /// match, for cube maps sampled without seamless filtering, faces of the standard
+    /// projection of one size without `--edge-pad`
+    #[arg(long, default_value_t = false)]
+    pub fix_edges: bool,
+    /// mirror each face left to right, after `--rotate`
+    #[arg(long, default_value_t = false)]
+    pub flip_x: bool,
+    /// mirror each face top to bottom, after `--rotate`, faces are saved with the origin at the
+    /// top left (directx, vulkan and gl cubemaps), flip for loaders that upload images bottom
+    /// up like webgl with `UNPACK_FLIP_Y_WEBGL`
+    #[arg(long, default_value_t = false)]
+    pub flip_y: bool,
+    /// samples per output pixel (1, 4, 9 or 16) averaged to reduce aliasing,
+    /// conversion time grows linearly with it
+    #[arg(long, default_value_t = 1, value_parser = parse_samples)]
+    pub samples: u32,
+    /// take the `--samples` sub-samples only for pixels whose neighbors are more than a texel
+    /// apart in the input image (minified regions and the poles), others get one sample
+    #[arg(long, default_value_t = false)]
+    pub adaptive_aa: bool,
+    /// longitude (degrees, right of the image center is positive) the front side faces, a cheaper
+    /// way to spin the panorama about its poles than `--yaw`
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    pub center_longitude: f32,
+    /// vertical coverage (degrees) of the input image from the zenith down, for partial panoramas
+    /// without the ground, the faces get `--fill-color` below it, the input is
+    /// width * fov / 360 px high
+    #[arg(long, default_value_t = 180.0)]
+    pub vertical_fov: f32,
+    /// rotation (degrees) of the view about the vertical axis, applied first
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    pub yaw: f32,
+    /// rotation (degrees) of the view about the side axis, applied after yaw
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    pub pitch: f32,
+    /// rotation (degrees) of the view about the front axis, applied last
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    pub roll: f32,
+    /// the axis mapped to the vertical of the panorama, z for panoramas with the poles on
+    /// top and bottom, x or y for ones stored with the poles on the sides
+    #[arg(long, value_enum, default_value_t = PoleAxis::Z)]
+    pub pole_axis: PoleAxis,
+    /// read the input image bottom row first (v becomes 1 - v), for panoramas stored upside
+    /// down with the nadir on top
+    #[arg(long, default_value_t = false)]
+    pub invert_v: bool,
+    /// projection of the input image, cylindrical and mercator images can have any aspect
+    /// ratio, directions above and below them get `--fill-color`, mirrorball is a photo of a
+    /// chrome ball filling the image's shorter side, taken from the back looking at the front,
+    /// fisheye is an equidistant circular fisheye filling the shorter side looking at the front
+    #[arg(long, value_enum, default_value_t = InputProjection::Equirect)]
+    pub input_projection: InputProjection,
+    /// field of view (degrees, up to 360) of the lens of `--input-projection fisheye`,
+    /// directions outside of it get `--fill-color`
+    #[arg(long, default_value_t = 180.0)]
+    pub fisheye_fov: f32,
+    /// how directions are distributed over the faces, eac faces must be decoded with the
+    /// inverse warp (`atan`) by consumers
+    #[arg(long, value_enum, default_value_t = Projection::Standard)]
+    pub projection: Projection,
+    /// zoom of `--projection littleplanet`, larger values show less of the sky around the planet
+    #[arg(long, default_value_t = 1.0)]
+    pub planet_zoom: f32,
+    /// horizontal field of view (degrees) of `--projection view`
+    #[arg(long, default_value_t = 90.0)]
+    pub fov: f32,
+    /// width (px) of the image of `--projection view`
+    #[arg(long, default_value_t = 1280)]
+    pub width: u32,
+    /// height (px) of the image of `--projection view`
+    #[arg(long, default_value_t = 720)]
+    pub height: u32,
+    /// write a debug image instead of sampling the input, `uv` encodes the input uv each texel
+    /// samples as red and green, `normals` its direction (before `--yaw`, `--pitch` and
+    /// `--roll`) as rgb `dir * 0.5 + 0.5`, x front, y right and z down, `seams` samples as usual
+    /// and draws a 1 px border around each face colored by side to check how faces line up
+    #[arg(long, value_enum, default_value_t = DebugMode::Off)]
+    pub debug: DebugMode,
+    /// color (`RRGGBBAA` hex) of the pixels where sampling the source fails
+    #[arg(long, default_value = "000000ff")]
+    pub fill_color: FillColor,
+    /// background color (`RRGGBB` hex) transparent pixels are composited over for jpg faces,
+    /// which can't store alpha
+    #[arg(long, default_value = "000000")]
+    pub matte: FillColor,
+    /// multiply the colors of the saved faces by their alpha for compositors that expect
+    /// premultiplied alpha, jpg faces ignore it and are composited over `--matte` as usual
+    #[arg(long, default_value_t = false)]
+    pub premultiply: bool,
+    /// only sample the input inside `lon0,lon1,lat0,lat1` (degrees, longitude right of the image
+    /// center and latitude up are positive), directions outside get `--fill-color`, lon0 past
+    /// lon1 wraps around the back
+    #[arg(long, allow_hyphen_values = true)]
+    pub crop: Option<Crop>,
+    /// how the sides are laid out in the output images
+    #[arg(short, long, value_enum, default_value_t = Layout::Separate)]
+    pub layout: Layout,
+    /// sides to render and save, others are skipped, all sides are needed for packed layouts
+    /// and cubemap formats
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = Side::all())]
+    pub faces: Vec<Side>,
+    /// render and save only the sides of `--faces` seen by a camera looking along `--cull-yaw`
+    /// and `--cull-pitch` of the faces (before `--yaw`, `--pitch` and `--roll`) with a view cone
+    /// of `--cull-fov`, for previews from a known viewpoint
+    #[arg(long, default_value_t = false)]
+    pub faces_only_visible: bool,
+    /// direction (degrees, toward the right of the front positive) of the camera of
+    /// `--faces-only-visible` about the vertical axis
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    pub cull_yaw: f32,
+    /// direction (degrees, up positive) of the camera of `--faces-only-visible` above the
+    /// horizon, applied after the yaw
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    pub cull_pitch: f32,
+    /// full angle (degrees, up to 360) of the view cone of `--faces-only-visible`, use the
+    /// diagonal field of view of the camera so its corners are covered
+    #[arg(long, default_value_t = 90.0)]
+    pub cull_fov: f32,
+    /// order of the sides in the row layout
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [Side::Right, Side::Left, Side::Top, Side::Bottom, Side::Front, Side::Back])]
+    pub face_order: Vec<Side>,
+    /// how the face files are named in the separate layout and read with `--reverse`
+    #[arg(long, value_enum, default_value_t = NamingScheme::Compass)]
+    pub naming: NamingScheme,
+    /// decode srgb input images to linear light before sampling and encode the faces back to srgb,
+    /// ignored for hdr input images and output formats
+    #[arg(long, default_value_t = false)]
+    pub linearize: bool,
+    /// color space of the saved faces, srgb png faces are tagged with `sRGB`, `gAMA` and `cHRM`
+    /// chunks, linear decodes srgb input images like `--linearize` but saves the faces without
+    /// encoding them back, tagged with a gamma of 1 and without the input's icc profile, hdr
+    /// formats are always linear
+    #[arg(long, value_enum, default_value_t = ColorSpace::Srgb)]
+    pub color_space: ColorSpace,
+    /// sample only the first channel of the input image as floats and save single channel
+    /// faces, for fields like depth, exr and tiff faces of the separate layout only
+    #[arg(long, default_value_t = false)]
+    pub scalar: bool,
+    /// quality (1-100) of jpg output images, other formats ignore it (webp is always lossless)
+    #[arg(short, long, default_value_t = 75, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub quality: u8,
+    /// bits per channel of the output images, formats other than png fall back to 8
+    #[arg(long, value_enum, default_value_t = BitDepth::Eight)]
+    pub bit_depth: BitDepth,
+    /// tone mapping operator applied to the input image, skipped for hdr output formats, 8 and
+    /// 16-bit input images are gamma decoded to float first
+    #[arg(short, long, value_enum, default_value_t = ToneMapping::None)]
+    pub tone_map_operator: ToneMapping,
+    /// exposure multiplier applied to the input image before tone mapping
+    #[arg(short, long, default_value_t = 1.0)]
+    pub exposure: f32,
+    /// derive the exposure of the input image from its log-average luminance, mapped to
+    /// mid-gray before tone mapping, `--exposure` multiplies on top of it
+    #[arg(long, default_value_t = false)]
+    pub auto_exposure: bool,
+    /// add an ordered (bayer) dither of +-0.5 steps where float colors are quantized to 8 bits,
+    /// tone mapping and encoding linear samples back to srgb, so smooth gradients don't band
+    #[arg(long, default_value_t = false)]
+    pub dither: bool,
+    /// resample input images whose width isn't exact 2 times of their height instead of erroring
+    #[arg(long, default_value_t = false)]
+    pub allow_non_2to1: bool,
+    /// downscale input images wider or taller than this (px) before converting, to twice the
+    /// resolution the faces need and at most this, bounds the memory and time huge sources
+    /// take with small faces
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_input_dimension: Option<u32>,
+    /// read this layer (a part's name or a channel prefix like `beauty` of `beauty.R`) of a
+    /// multi-layer or multi-part exr input image, instead of the first one
+    #[arg(long)]
+    pub exr_layer: Option<String>,
+    /// convert every image listed (one path per line, empty lines and lines starting with `#`
+    /// are skipped) in this text file into a subdirectory (named by the image) of the output
+    /// directory, like a directory input
+    #[arg(long)]
+    pub input_list: Option<PathBuf>,
+    /// read options from a toml file of `key = value` lines named like the long options
+    /// (`size = 1024`, `tone_map_operator = "aces"`, `rotate = true`, `faces = ["front",
+    /// "back"]`), options given on the command line override the file's
+    #[arg(long = "config")]
+    pub config_file: Option<PathBuf>,
+    /// split each face of the separate layout into an n x n grid saved as `{side}_{x}_{y}.{format}`,
+    /// described by an `index.json` manifest
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub tiles: u32,
+    /// save the box filtered mip chain of each face of the separate layout down to 1x1 as
+    /// `{side}_mip{level}.{format}` instead of the face, level 0 is the full size
+    #[arg(long, default_value_t = false)]
+    pub mips: bool,
+    /// write an `index.json` describing the saved faces and the config next to them
+    #[arg(long, default_value_t = false)]
+    pub manifest: bool,
+    /// print a histogram of the luminance of the input image, in stops for hdr input images
+    #[arg(long, default_value_t = false)]
+    pub histogram: bool,
+    /// print the min, max and mean luminance (1 is full intensity) of each saved face and the
+    /// fraction of its pixels that clip, to catch faces that came out black or overexposed
+    #[arg(long, default_value_t = false)]
+    pub stats: bool,
+    /// also save `contact_sheet.png`, small previews of the faces labeled with their sides
+    #[arg(long, default_value_t = false)]
+    pub contact_sheet: bool,
+    /// print the sha-256 of each saved image after saving, in the format of `sha256sum`
+    #[arg(long, default_value_t = false)]
+    pub print_hashes: bool,
+    /// number of images converted at the same time when the input is a directory
+    #[arg(short, long, default_value_t = 1)]
+    pub jobs: usize,
+    /// render and save the faces one at a time so only one face is in memory besides the
+    /// source, separate layout only, slower on machines with many cores
+    #[arg(long, default_value_t = false)]
+    pub low_memory: bool,
+    /// don't copy the icc profile of the input image into png, jpg and webp faces
+    #[arg(long, default_value_t = false)]
+    pub strip_metadata: bool,
+    /// icc profile embedded in the saved images, read from the input image
+    #[arg(skip)]
+    pub icc_profile: Option<Vec<u8>>,
+    /// print the files that would be written with their sizes, without converting or saving
+    #[arg(long, default_value_t = false)]
+    pub dry_run: bool,
+    /// fail instead of overwriting output files that already exist
+    #[arg(long, default_value_t = false)]
+    pub no_clobber: bool,
+    /// don't print progress and where the images are saved
+    #[arg(long, default_value_t = false)]
+    pub quiet: bool,
+    /// print how long each stage takes
+    #[arg(long, default_value_t = false)]
+    pub verbose: bool,
+    /// print how long each stage takes (seconds) to stderr as a json object per image
+    #[arg(long, default_value_t = false)]
+    pub timing_json: bool,
+    /// print one json object with the output directory, the saved files and their sizes and the
+    /// elapsed seconds to stdout instead of the messages, or one with the error
+    #[arg(long, default_value_t = false)]
+    pub json: bool,
+    /// treat input as a directory of six faces named by side like `--naming` (in any image
+    /// format) and rebuild an equirect image of `--format`
+    #[arg(long, default_value_t = false)]
+    pub reverse: bool,
+}
+impl Config {
+    /// whether the input image is read from stdin
+    pub fn reads_stdin(&self) -> bool {
+        self.input.as_os_str() == "-"
+    }
+    /// whether the output image is written to stdout
+    pub fn writes_stdout(&self) -> bool {
+        self.output.as_os_str() == "-"
+    }
+    /// whether 8 and 16-bit input images are sampled in linear light, with `--linearize` or
+    /// `--color-space linear`
+    pub fn samples_linear(&self) -> bool {
+        self.linearize || self.color_space == ColorSpace::Linear
+    }
+}
+/// size (px) of each side, in the order front, back, left, right, top, bottom
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FaceSizes(pub [u32; 6]);
+impl FaceSizes {
+    /// the size shared by all sides, if they are the same
+    pub fn uniform(&self) -> Option<u32> {
+        let [first, rest @ ..] = self.0;
+        rest.iter().all(|s| *s == first).then_some(first)
+    }
+}
+impl FromStr for FaceSizes {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let sizes = s
+            .split(',')
+            .map(|v| v.trim().parse::<u32>().map_err(|e| format!("{}: {}", v, e)))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        match sizes[..] {
+            [size] => Ok(Self([size; 6])),
+            [front, back, left, right, top, bottom] => {
+                Ok(Self([front, back, left, right, top, bottom]))
+            }
+            _ => Err(format!("expected 1 or 6 sizes, got {}", sizes.len())),
+        }
+    }
+}
+impl Display for FaceSizes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.uniform() {
+            Some(size) => write!(f, "{}", size),
+            None => {
+                let [front, back, left, right, top, bottom] = self.0;
+                write!(f, "{front},{back},{left},{right},{top},{bottom}")
+            }
+        }
+    }
+}
+
+/// clockwise quarter turn of a face
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    R90,
+    R180,
+    R270,
+}
+
+/// how a face is turned and then mirrored before saving
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Orientation {
+    pub rotation: Rotation,
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+impl Orientation {
+    pub fn rotated(rotation: Rotation) -> Self {
+        Self {
+            rotation,
+            ..Default::default()
+        }
+    }
+}
+
+/// the cube map conventions of `--convention`
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Convention {
+    Opengl,
+    Directx,
+    Vulkan,
+    Unity,
+    Unreal,
+    Blender,
+}
+impl Convention {
+    /// directions (x front, y right, z down) of the convention's +x, +y and +z axes
+    pub fn axes(&self) -> [Vector3; 3] {
+        let front = Vector3::new(1.0, 0.0, 0.0);
+        let right = Vector3::new(0.0, 1.0, 0.0);
+        let up = Vector3::new(0.0, 0.0, -1.0);
+        let back = front.scale(-1.0);
+        match self {
+            Convention::Opengl => [right, up, back],
+            // vulkan's cube map lookup is direct3d's
+            Convention::Directx | Convention::Vulkan | Convention::Unity => [right, up, front],
+            Convention::Unreal => [front, right, up],
+            Convention::Blender => [right, front, up],
+        }
+    }
+    /// maps the direction of each side to the direction of the axis it's saved as: front to
+    /// +z, left (the side facing +y) to -x and bottom to -y
+    pub fn sampling_matrix(&self) -> [[f32; 3]; 3] {
+        let [x, y, z] = self.axes();
+        let columns = [z, x.scale(-1.0), y.scale(-1.0)];
+        std::array::from_fn(|i| columns.map(|c| [c.x, c.y, c.z][i]))
+    }
+}
+
+/// orientations turning the sides sampled through `Convention::sampling_matrix` into the face
+/// images of the cube map lookup opengl, direct3d and vulkan share, stored top row first
+pub fn convention_orientations() -> HashMap<Side, Orientation> {
+    let flipped = |rotation| Orientation {
+        rotation,
+        flip_x: true,
+        flip_y: false,
+    };
+    HashMap::from([
+        (Side::Front, flipped(Rotation::None)),
+        (Side::Back, flipped(Rotation::None)),
+        (Side::Left, flipped(Rotation::None)),
+        (Side::Right, flipped(Rotation::None)),
+        (Side::Top, flipped(Rotation::R90)),
+        (Side::Bottom, flipped(Rotation::R270)),
+    ])
+}
+
+/// orientation of each side given as `side=angle[+flipx][+flipy],...`, sides not listed are
+/// kept as they are
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FaceOrientations(pub HashMap<Side, Orientation>);
+impl FromStr for FaceOrientations {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut orientations = HashMap::new();
+        for entry in s.split(',') {
+            let (side, value) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected side=angle, got {:?}", entry))?;
+            let mut orientation = Orientation::default();
+            for part in value.split('+') {
+                match part.trim() {
+                    "0" => orientation.rotation = Rotation::None,
+                    "90" => orientation.rotation = Rotation::R90,
+                    "180" => orientation.rotation = Rotation::R180,
+                    "270" => orientation.rotation = Rotation::R270,
+                    "flipx" => orientation.flip_x = true,
+                    "flipy" => orientation.flip_y = true,
+                    other => {
+                        return Err(format!(
+                            "unknown orientation {:?}, expected 0, 90, 180, 270, flipx or flipy",
+                            other
+                        ))
+                    }
+                }
+            }
+            orientations.insert(side.trim().parse()?, orientation);
+        }
+        Ok(Self(orientations))
+    }
+}
+
+/// longitude and latitude range (degrees) of `--crop`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Crop {
+    pub lon: (f32, f32),
+    pub lat: (f32, f32),
+}
+impl FromStr for Crop {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<f32>().map_err(|e| format!("{}: {}", v, e)))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let [lon0, lon1, lat0, lat1] = values[..] else {
+            return Err(format!(
+                "expected lon0,lon1,lat0,lat1, got {} values",
+                values.len()
+            ));
+        };
+        if ![lon0, lon1]
+            .iter()
+            .all(|lon| (-180.0..=180.0).contains(lon))
+        {
+            return Err("longitudes should be between -180 and 180".to_owned());
+        }
+        if !(-90.0..=90.0).contains(&lat0) || !(-90.0..=90.0).contains(&lat1) || lat0 >= lat1 {
+            return Err("latitudes should be between -90 and 90 with lat0 below lat1".to_owned());
+        }
+        Ok(Self {
+            lon: (lon0, lon1),
+            lat: (lat0, lat1),
+        })
+    }
+}
+
+/// an 8-bit rgba color written as hex `RRGGBBAA` (or `RRGGBB`, opaque)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FillColor(pub [u8; 4]);
+impl FromStr for FillColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let hex = s.trim_start_matches('#');
+        if !(hex.len() == 6 || hex.len() == 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("expected RRGGBB or RRGGBBAA, got {}", s));
+        }
+        let mut color = [u8::MAX; 4];
+        for (i, c) in color.iter_mut().enumerate().take(hex.len() / 2) {
+            *c = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|e| e.to_string())?;
+        }
+        Ok(Self(color))
+    }
+}
+impl Display for FillColor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [r, g, b, a] = self.0;
+        write!(f, "{r:02x}{g:02x}{b:02x}{a:02x}")
+    }
+}
+
+/// replace `{stem}`, `{format}` and `{size}` in the output directory `template`, the stem of
+/// stdin is `stdin`
+pub fn expand_output_path(template: &str, input: &Path, config: &Config) -> PathBuf {
+    let stem = if input.as_os_str() == "-" {
+        "stdin".into()
+    } else {
+        input.file_stem().unwrap_or_default().to_string_lossy()
+    };
+    template
+        .replace("{stem}", &stem)
+        .replace("{format}", &config.format.default.to_string())
+        .replace("{size}", &config.size.to_string())
+        .into()
+}
+
+/// size (px) of a side of the output images
+pub fn face_size(config: &Config, side: Side) -> u32 {
+    let [front, back, left, right, top, bottom] = config.size.0;
+    match side {
+        Side::Front => front,
+        Side::Back => back,
+        Side::Left => left,
+        Side::Right => right,
+        Side::Top => top,
+        Side::Bottom => bottom,
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Jpg,
+    Png,
+    /// lossless, pixel exact like png but usually a third to a quarter of its size, still a few
+    /// times larger than jpg, lossy webp isn't supported
+    Webp,
+    /// all sides in one uncompressed rgba8 cubemap dds file
+    Dds,
+    /// all sides in one uncompressed rgba8 cubemap ktx2 file for vulkan and webgpu, srgb unless
+    /// `--color-space linear`
+    Ktx2,
+    /// linear float faces, tone mapping is skipped
+    Exr,
+    /// linear float faces without alpha, tone mapping is skipped
+    Hdr,
+    /// linear float faces, tone mapping is skipped
+    Tiff,
+}
+/// output format of each side
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Formats {
+    /// format of the sides not in `per_side`
+    pub default: OutputFormat,
+    pub per_side: HashMap<Side, OutputFormat>,
+}
+impl Formats {
+    pub fn of(&self, side: Side) -> OutputFormat {
+        self.per_side.get(&side).copied().unwrap_or(self.default)
+    }
+    /// every format in use, `default` first
+    pub fn all(&self) -> impl Iterator<Item = OutputFormat> + '_ {
+        std::iter::once(self.default).chain(self.per_side.values().copied())
+    }
+    /// whether all sides have the same format
+    pub fn is_uniform(&self) -> bool {
+        self.all().all(|format| format == self.default)
+    }
+    /// whether all formats support 16-bit output
+    pub fn supports_16_bit(&self) -> bool {
+        self.all().all(|format| format.supports_16_bit())
+    }
+    /// whether any format stores all sides in one file
+    pub fn is_cubemap_container(&self) -> bool {
+        self.all().any(|format| format.is_cubemap_container())
+    }
+    /// whether the faces are float, see `mixes_hdr`
+    pub fn is_hdr(&self) -> bool {
+        self.default.is_hdr()
+    }
+    /// whether some formats are float and others aren't, faces are rendered as one or the other
+    pub fn mixes_hdr(&self) -> bool {
+        self.all()
+            .any(|format| format.is_hdr() != self.default.is_hdr())
+    }
+}
+impl From<OutputFormat> for Formats {
+    fn from(default: OutputFormat) -> Self {
+        Self {
+            default,
+            per_side: HashMap::new(),
+        }
+    }
+}
+impl FromStr for Formats {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let format = |value: &str| clap::ValueEnum::from_str(value.trim(), true);
+        if !s.contains('=') {
+            return format(s).map(Self::from);
+        }
+        let mut formats = Self::from(OutputFormat::Png);
+        for entry in s.split(',') {
+            let (side, value) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected side=format, got {:?}", entry))?;
+            match side.trim() {
+                "default" => formats.default = format(value)?,
+                side => {
+                    formats.per_side.insert(side.parse()?, format(value)?);
+                }
+            }
+        }
+        Ok(formats)
+    }
+}
+
+/// the `image` format of the same name, ktx2 has none
+impl TryFrom<OutputFormat> for image::ImageFormat {
+    type Error = ImageError;
+
+    fn try_from(value: OutputFormat) -> std::result::Result<Self, Self::Error> {
+        Ok(match value {
+            OutputFormat::Jpg => image::ImageFormat::Jpeg,
+            OutputFormat::Png => image::ImageFormat::Png,
+            OutputFormat::Webp => image::ImageFormat::WebP,
+            OutputFormat::Dds => image::ImageFormat::Dds,
+            OutputFormat::Exr => image::ImageFormat::OpenExr,
+            OutputFormat::Hdr => image::ImageFormat::Hdr,
+            OutputFormat::Tiff => image::ImageFormat::Tiff,
+            OutputFormat::Ktx2 => {
+                return Err(ImageError::Unsupported(
+                    UnsupportedError::from_format_and_kind(
+                        ImageFormatHint::Name("ktx2".to_owned()),
+                        UnsupportedErrorKind::Format(ImageFormatHint::Name("ktx2".to_owned())),
+                    ),
+                ))
+            }
+        })
+    }
+}
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputFormat::Jpg => write!(f, "jpg"),
+            OutputFormat::Png => write!(f, "png"),
+            OutputFormat::Webp => write!(f, "webp"),
+            OutputFormat::Dds => write!(f, "dds"),
+            OutputFormat::Ktx2 => write!(f, "ktx2"),
+            OutputFormat::Exr => write!(f, "exr"),
+            OutputFormat::Hdr => write!(f, "hdr"),
+            OutputFormat::Tiff => write!(f, "tiff"),
+        }
+    }
+}
+impl OutputFormat {
+    /// whether this format can store 16 bits per channel
+    pub fn supports_16_bit(&self) -> bool {
+        matches!(self, OutputFormat::Png)
+    }
+    /// whether this format stores all sides in one file
+    pub fn is_cubemap_container(&self) -> bool {
+        matches!(self, OutputFormat::Dds | OutputFormat::Ktx2)
+    }
+    /// whether this format stores float (hdr) pixels
+    pub fn is_hdr(&self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Exr | OutputFormat::Hdr | OutputFormat::Tiff
+        )
+    }
+    /// whether an icc profile can be embedded in the images
+    pub fn supports_icc(&self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Png | OutputFormat::Jpg | OutputFormat::Webp
+        )
+    }
+}
+#[derive(clap::ValueEnum, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum Projection {
+    /// standard cubemap, faces are sampled evenly on the cube surface
+    Standard,
+    /// equiangular cubemap, faces are sampled evenly in angle
+    Eac,
+    /// one square stereographic "little planet" image looking down at the nadir,
+    /// saved as `planet.{format}` instead of the faces
+    #[value(name = "littleplanet")]
+    LittlePlanet,
+    /// one rectilinear image like a virtual camera, see `--fov`, `--width` and `--height`,
+    /// saved as `view.{format}` instead of the faces
+    View,
+}
+#[derive(clap::ValueEnum, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum BitDepth {
+    #[value(name = "8")]
+    Eight,
+    #[value(name = "16")]
+    Sixteen,
+}
+
+/// what `--debug` writes into the faces
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugMode {
+    Off,
+    Uv,
+    Normals,
+    Seams,
+}
+
+/// how the 8 and 16-bit channels of the saved faces encode light
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    Front,
+    Back,
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+impl Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Side::Front => write!(f, "front"),
+            Side::Back => write!(f, "back"),
+            Side::Left => write!(f, "left"),
+            Side::Right => write!(f, "right"),
+            Side::Top => write!(f, "top"),
+            Side::Bottom => write!(f, "bottom"),
+        }
+    }
+}
+impl FromStr for Side {
+    type Err = String;
+
+    /// the lowercase names `Display` writes
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Side::all()
+            .into_iter()
+            .find(|side| side.to_string() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown side {:?}, expected one of front, back, left, right, top, bottom",
+                    s
+                )
+            })
+    }
+}
+impl Side {
+    /// all sides in the order front, back, left, right, top, bottom
+    pub fn all() -> [Side; 6] {
+        use Side::*;
+        [Front, Back, Left, Right, Top, Bottom]
+    }
+}
+
+/// how the face files are named
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NamingScheme {
+    /// front, back, left, right, top, bottom
+    Compass,
+    /// posx, negx, posy, negy, posz, negz
+    Axis,
+    /// front, back, left, right, up, down like the slots of a 6 sided skybox
+    Unity,
+    /// px, nx, py, ny, pz, nz
+    Opengl,
+}
+
+/// file name (without extension) of a side, the axis schemes follow the y-up cubemap
+/// convention also used for dds: +x right, -x left, +y top, -y bottom, +z front, -z back
+pub fn filename(side: Side, scheme: NamingScheme) -> &'static str {
+    use NamingScheme::*;
+    use Side::*;
+    match (scheme, side) {
+        (Compass, Front) => "front",
+        (Compass, Back) => "back",
+        (Compass, Left) => "left",
+        (Compass, Right) => "right",
+        (Compass, Top) => "top",
+        (Compass, Bottom) => "bottom",
+        (Axis, Right) => "posx",
+        (Axis, Left) => "negx",
+        (Axis, Top) => "posy",
+        (Axis, Bottom) => "negy",
+        (Axis, Front) => "posz",
+        (Axis, Back) => "negz",
+        (Unity, Front) => "front",
+        (Unity, Back) => "back",
+        (Unity, Left) => "left",
+        (Unity, Right) => "right",
+        (Unity, Top) => "up",
+        (Unity, Bottom) => "down",
+        (Opengl, Right) => "px",
+        (Opengl, Left) => "nx",
+        (Opengl, Top) => "py",
+        (Opengl, Bottom) => "ny",
+        (Opengl, Front) => "pz",
+        (Opengl, Back) => "nz",
+    }
+}
+
+/// read the six faces named by `naming` (any image extension, case insensitive) from `dir`,
+/// converted to pixels by `into_face`, they have to be square and of one size
+pub fn load_cubemap_dir<P: Pixel>(
+    dir: &Path,
+    naming: NamingScheme,
+    into_face: fn(DynamicImage) -> FaceBuffer<P>,
+) -> Result<Cubemap<P>> {
+    let mut paths: Vec<Vec<PathBuf>> = vec![Vec::new(); 6];
+    for entry in std::fs::read_dir(dir)
+        .map_err(|e| anyhow::anyhow!(r#"Failed to read "{}": {}"#, dir.display(), e))?
+    {
+        let path = entry?.path();
+        if !path.is_file() || image::ImageFormat::from_path(&path).is_err() {
+            continue;
+        }
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        if let Some(i) = Side::all()
+            .iter()
+            .position(|side| stem.eq_ignore_ascii_case(filename(*side, naming)))
+        {
+            paths[i].push(path);
+        }
+    }
+    let mut faces: Vec<(FaceBuffer<P>, Side)> = Vec::with_capacity(6);
+    for (side, paths) in Side::all().into_iter().zip(paths) {
+        let path = match paths.as_slice() {
+            [path] => path,
+            [] => bail!(
+                r#"No image named "{}" for the {} side in "{}"."#,
+                filename(side, naming),
+                side,
+                dir.display()
+            ),
+            _ => {
+                let names: Vec<_> = paths.iter().map(|p| p.display().to_string()).collect();
+                bail!(
+                    "Several images for the {} side: {}.",
+                    side,
+                    names.join(", ")
+                )
+            }
+        };
+        let img = image::open(path)
+            .map_err(|e| anyhow::anyhow!(r#"Failed to read "{}": {}"#, path.display(), e))?;
+        let (width, height) = img.dimensions();
+        if width != height {
+            bail!(
+                r#"Face "{}" is {}x{}, faces need to be square."#,
+                path.display(),
+                width,
+                height
+            );
+        }
+        if let Some((first, _)) = faces.first() {
+            let size = first.width();
+            if width != size {
+                bail!(
+                    r#"Face "{}" is {}x{}, the {} face is {}x{}."#,
+                    path.display(),
+                    width,
+                    height,
+                    Side::Front,
+                    size,
+                    size
+                );
+            }
+        }
+        faces.push((into_face(img), side));
+    }
+    Ok(Cubemap::from_entries(faces))
+}
+
+/// normalize a decoded image to rgba so the rest of the pipeline sees one pixel type per range,
+/// grayscale and paletted images become `Rgba8`, 16-bit ones `Rgba16` and float ones `Rgba32F`
+pub fn to_samplable(img: DynamicImage) -> DynamicImage {
+    use DynamicImage::*;
+    match img {
+        ImageRgba8(_) | ImageRgba16(_) | ImageRgba32F(_) => img,
+        ImageRgb32F(_) => ImageRgba32F(img.into_rgba32f()),
+        ImageLuma16(_) | ImageLumaA16(_) | ImageRgb16(_) => ImageRgba16(img.into_rgba16()),
+        _ => ImageRgba8(img.into_rgba8()),
+    }
+}
+
+/// decode the input image with its icc profile, or the `--exr-layer` of an exr image, single
+/// channel float images of `--scalar` included
+pub fn decode_input<R: BufRead + Seek>(
+    config: &Config,
+    reader: image::io::Reader<R>,
+) -> Result<(DynamicImage, Option<Vec<u8>>)> {
+    match &config.exr_layer {
+        Some(layer) => Ok((
+            exr_layers::read_exr_layer(reader.into_inner(), layer)?,
+            None,
+        )),
+        None if config.scalar => scalar::decode_scalar(reader),
+        None => Ok(decode_with_icc(reader)?),
+    }
+}
+
+/// check the size of an equirect image, width should be exact 2 times of height
+pub fn validate_equirect(width: u32, height: u32) -> Result<()> {
+    if width != height * 2 {
+        bail!(
+            "Image width should be exact 2 times of image height, got {}x{}.",
+            width,
+            height
+        );
+    }
+    Ok(())
+}
+
+/// height (px) of a panorama `width` px wide that covers `--vertical-fov`, width / 2 when it
+/// covers all of it
+pub fn panorama_height(config: &Config, width: u32) -> u32 {
+    if config.vertical_fov >= 180.0 {
+        width / 2
+    } else {
+        (width as f32 * config.vertical_fov / 360.0)
+            .round()
+            .max(1.0) as u32
+    }
+}
+
+/// check the size of the input image against `--vertical-fov`, rounding is allowed to be off by
+/// a pixel for partial panoramas, other input projections than equirect have no fixed size
+pub fn validate_panorama(config: &Config, width: u32, height: u32) -> Result<()> {
+    if config.input_projection != InputProjection::Equirect {
+        return Ok(());
+    }
+    if config.vertical_fov >= 180.0 {
+        return validate_equirect(width, height);
+    }
+    let expected = panorama_height(config, width);
+    if height.abs_diff(expected) > 1 {
+        bail!(
+            "Image height should be {} for a vertical fov of {} degrees, got {}x{}.",
+            expected,
+            config.vertical_fov,
+            width,
+            height
+        );
+    }
+    Ok(())
+}
+
+/// the size an input image of `width` x `height` is downscaled to with `--max-input-dimension`,
+/// `None` if it isn't larger than the limit or the faces need all of it, the width the faces
+/// need is four faces around the equator (or the width of `--projection view` over its fov)
+pub fn limited_input_size(config: &Config, width: u32, height: u32) -> Option<(u32, u32)> {
+    let limit = config.max_input_dimension?;
+    let largest = width.max(height);
+    if largest <= limit {
+        return None;
+    }
+    let needed = match config.projection {
+        Projection::View => config.width as f32 * 360.0 / config.fov,
+        _ => 4.0 * (config.size.0.iter().max().copied().unwrap_or(0) + 2 * config.edge_pad) as f32,
+    };
+    let scale = (2.0 * needed / width as f32).min(limit as f32 / largest as f32);
+    if scale >= 1.0 {
+        return None;
+    }
+    let scaled = |n: u32| ((n as f32 * scale).round() as u32).max(1);
+    Some((scaled(width), scaled(height)))
+}
+
+/// downscale the input image to `limited_input_size` with a lanczos filter, images within the
+/// limit are returned as is
+pub fn limit_input_size(config: &Config, img: DynamicImage) -> DynamicImage {
+    match limited_input_size(config, img.width(), img.height()) {
+        Some((width, height)) => {
+            img.resize_exact(width, height, image::imageops::FilterType::Lanczos3)
+        }
+        None => img,
+    }
+}
+
+/// tone map an image to 8 bits with the configured operator, images that aren't float are gamma
+/// decoded to float first (the inverse of the operators' encoding) so all bit depths match
+pub fn tone_map(config: &Config, img: DynamicImage) -> DynamicImage {
+    let operator = config.tone_map_operator;
+    if operator == ToneMapping::None {
+        return img;
+    }
+    let img = match img {
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => img,
+        img => {
+            let mut float = img.into_rgba32f();
+            float.par_chunks_mut(4).for_each(|p| {
+                for c in &mut p[..3] {
+                    *c = c.powf(2.2);
+                }
+            });
+            DynamicImage::ImageRgba32F(float)
+        }
+    };
+    let exposure = if config.auto_exposure {
+        // maps the average luminance to mid-gray
+        config.exposure * 0.18 / average_luminance(&img)
+    } else {
+        config.exposure
+    };
+    let dither = config.dither;
+    let dither_at = |x, y| if dither { bayer_dither(x, y) } else { 0.0 };
+    match img {
+        DynamicImage::ImageRgb32F(img) => {
+            let mut out = RgbaImage::new(img.width(), img.height());
+            let width = img.width();
+            out.par_chunks_mut(4)
+                .zip(img.par_pixels())
+                .enumerate()
+                .for_each(|(i, (dst, src))| {
+                    let dither = dither_at(i as u32 % width, i as u32 / width);
+                    dst.copy_from_slice(&operator.map_rgb_dithered(*src, exposure, dither).0)
+                });
+            DynamicImage::ImageRgba8(out)
+        }
+        DynamicImage::ImageRgba32F(img) => {
+            let mut out = RgbaImage::new(img.width(), img.height());
+            let width = img.width();
+            out.par_chunks_mut(4)
+                .zip(img.par_pixels())
+                .enumerate()
+                .for_each(|(i, (dst, src))| {
+                    let dither = dither_at(i as u32 % width, i as u32 / width);
+                    dst.copy_from_slice(&operator.map_rgba_dithered(*src, exposure, dither).0)
+                });
+            DynamicImage::ImageRgba8(out)
+        }
+        _ => unreachable!("converted to float above"),
+    }
+}
+
+/// decode an srgb image to linear light, float images are returned as is since they are linear already
+pub fn linearize(img: &DynamicImage) -> Rgba32FImage {
+    let is_float = matches!(
+        img,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+    let mut img = img.to_rgba32f();
+    if !is_float {
+        img.par_pixels_mut().for_each(|p| {
+            let [r, g, b, a] = p.0;
+            p.0 = [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a];
+        });
+    }
+    img
+}
+
+/// encode a linear light face to `--color-space`, srgb faces are delinearized and linear ones
+/// are only quantized, 8-bit faces are dithered with `--dither`
+pub fn encode_color_space<P: SamplePixel>(config: &Config, img: &Rgba32FImage) -> FaceBuffer<P> {
+    let dither = config.dither && P::CHANNEL_MAX == u8::MAX as f32;
+    match config.color_space {
+        ColorSpace::Srgb => delinearize_dithered(img, dither),
+        ColorSpace::Linear => ImageBuffer::from_fn(img.width(), img.height(), |x, y| {
+            let offset = if dither { bayer_dither(x, y) } else { 0.0 };
+            let [r, g, b, a] = img.get_pixel(x, y).0.map(|c| c * P::CHANNEL_MAX);
+            P::from_f32([r + offset, g + offset, b + offset, a])
+        }),
+    }
+}
+
+/// multiply the red, green and blue channels of each pixel by its alpha
+pub fn premultiply_alpha<P: SamplePixel>(img: &mut FaceBuffer<P>) {
+    for pixel in img.pixels_mut() {
+        let [r, g, b, a] = pixel.to_f32();
+        let alpha = (a / P::CHANNEL_MAX).clamp(0.0, 1.0);
+        *pixel = P::from_f32([r * alpha, g * alpha, b * alpha, a]);
+    }
+}
+
+/// encode a linear light image to srgb
+pub fn delinearize<P: SamplePixel>(img: &Rgba32FImage) -> FaceBuffer<P> {
+    delinearize_dithered(img, false)
+}
+
+/// same as `delinearize`, with `bayer_dither` added to the color channels before rounding
+fn delinearize_dithered<P: SamplePixel>(img: &Rgba32FImage, dither: bool) -> FaceBuffer<P> {
+    ImageBuffer::from_fn(img.width(), img.height(), |x, y| {
+        let [r, g, b, a] = img.get_pixel(x, y).0;
+        let offset = if dither { bayer_dither(x, y) } else { 0.0 };
+        let srgb = [linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b)]
+            .map(|c| c * P::CHANNEL_MAX + offset);
+        P::from_f32([srgb[0], srgb[1], srgb[2], a * P::CHANNEL_MAX])
+    })
+}
+
+/// convert 1 equirect image to cubemaps (6 squared images, or the sides in `faces`)
+pub fn convert(config: &Config, img: &DynamicImage) -> Vec<(ImageBufferData, Side)> {
+    convert_view(config, img)
+}
+
+/// same as `convert`, but returns the sides as a `Cubemap`, all sides are rendered
+/// regardless of `faces`
+pub fn convert_cubemap(config: &Config, img: &DynamicImage) -> Cubemap {
+    let config = Config {
+        faces: Side::all().to_vec(),
+        ..config.clone()
+    };
+    Cubemap::from_entries(convert_view(&config, img))
+}
+
+/// convert 1 equirect image to a single side of the cubemaps
+pub fn convert_side(config: &Config, img: &DynamicImage, side: Side) -> ImageBufferData {
+    convert_side_view(config, img, side)
+}
+
+/// same as `convert`, but the faces keep the pixel type of the source,
+/// e.g. pass `img.to_rgba16()` to get 16-bit faces
+pub fn convert_view<I>(config: &Config, img: &I) -> Vec<(FaceBuffer<I::Pixel>, Side)>
+where
+    I: GenericImageView + Sync,
+    I::Pixel: SamplePixel,
+{
+    convert_view_with_progress(config, img, &AtomicU64::new(0))
+}
+
+/// same as `convert_view`, `progress` is increased by the number of pixels done as faces are rendered
+pub fn convert_view_with_progress<I>(
+    config: &Config,
+    img: &I,
+    progress: &AtomicU64,
+) -> Vec<(FaceBuffer<I::Pixel>, Side)>
+where
+    I: GenericImageView + Sync,
+    I::Pixel: SamplePixel,
+{
+    let mips = mips_for(config, img);
+    let mut faces: Vec<_> = config
+        .faces
+        .par_iter()
+        .map(|side| (render_side(config, img, &mips, *side, progress), *side))
+        .collect();
+    if config.fix_edges {
+        fix_cube_edges(&mut faces);
+        // the borders are averaged with the neighbors' too
+        for (face, side) in &mut faces {
+            draw_seam(config, face, *side);
+        }
+    }
+    faces
+}
+
+/// render the faces one at a time and hand each to `each` as soon as it's done, so besides the
+/// source only one face is in memory, stops at the first error `each` returns
+pub fn convert_each<I>(
+    config: &Config,
+    img: &I,
+    each: impl FnMut(FaceBuffer<I::Pixel>, Side) -> Result<()>,
+) -> Result<()>
+where
+    I: GenericImageView + Sync,
+    I::Pixel: SamplePixel,
+{
+    convert_each_with_progress(config, img, &AtomicU64::new(0), each)
+}
+
+/// same as `convert_each`, `progress` is increased by the number of pixels done as faces are rendered
+pub fn convert_each_with_progress<I>(
+    config: &Config,
+    img: &I,
+    progress: &AtomicU64,
+    mut each: impl FnMut(FaceBuffer<I::Pixel>, Side) -> Result<()>,
+) -> Result<()>
+where
+    I: GenericImageView + Sync,
+    I::Pixel: SamplePixel,
+{
+    let mips = mips_for(config, img);
+    for side in &config.faces {
+        each(render_side(config, img, &mips, *side, progress), *side)?;
+    }
+    Ok(())
+}
+
+/// same as `convert_side`, but the face keeps the pixel type of the source
+pub fn convert_side_view<I>(config: &Config, img: &I, side: Side) -> FaceBuffer<I::Pixel>
+where
+    I: GenericImageView + Sync,
+    I::Pixel: SamplePixel,
+{
+    render_side(
+        config,
+        img,
+        &mips_for(config, img),
+        side,
+        &AtomicU64::new(0),
+    )
+}
+
+/// the mip levels `--interpolation trilinear` samples, empty for other interpolations
+fn mips_for<I>(config: &Config, img: &I) -> Vec<FaceBuffer<I::Pixel>>
+where
+    I: GenericImageView,
+    I::Pixel: SamplePixel,
+{
+    match config.interpolation {
+        Interpolation::Trilinear => build_mip_levels(img),
+        _ => Vec::new(),
+    }
+}
+
+fn render_side<I>(
+    config: &Config,
+    img: &I,
+    mips: &[FaceBuffer<I::Pixel>],
+    side: Side,
+    progress: &AtomicU64,
+) -> FaceBuffer<I::Pixel>
+where
+    I: GenericImageView + Sync,
+    I::Pixel: SamplePixel,
+{
+    let (padded, direction) = side_direction(config, side);
+    let mut face = render_rect(config, img, mips, (padded, padded), direction, progress);
+    draw_seam(config, &mut face, side);
+    face
+}
+
+/// with `--debug seams`, draw the border of a face in the color of its side: front red, back
+/// cyan, left green, right magenta, top blue and bottom yellow
+fn draw_seam<P: SamplePixel>(config: &Config, face: &mut FaceBuffer<P>, side: Side) {
+    if config.debug != DebugMode::Seams {
+        return;
+    }
+    let color = debug_pixel(
+        config,
+        match side {
+            Side::Front => [1.0, 0.0, 0.0],
+            Side::Back => [0.0, 1.0, 1.0],
+            Side::Left => [0.0, 1.0, 0.0],
+            Side::Right => [1.0, 0.0, 1.0],
+            Side::Top => [0.0, 0.0, 1.0],
+            Side::Bottom => [1.0, 1.0, 0.0],
+        },
+    );
+    let (width, height) = face.dimensions();
+    for x in 0..width {
+        face.put_pixel(x, 0, color);
+        face.put_pixel(x, height - 1, color);
+    }
+    for y in 0..height {
+        face.put_pixel(0, y, color);
+        face.put_pixel(width - 1, y, color);
+    }
+}
+
+/// size (px) of a side with its padding, and the direction each of its texels samples
+fn side_direction(config: &Config, side: Side) -> (u32, impl Fn(f32, f32) -> Vector3 + Sync) {
+    let size_int = face_size(config, side);
+    let size = size_int as f32;
+    let projection = config.projection;
+    // the padding keeps sampling past the face edges, so it matches the neighboring faces
+    let pad = config.edge_pad as f32;
+    let padded = size_int + 2 * config.edge_pad;
+    let direction = move |xf: f32, yf: f32| {
+        let (xf, yf) = (xf - pad, yf - pad);
+        let (xf, yf) = match projection {
+            Projection::Standard | Projection::LittlePlanet | Projection::View => (xf, yf),
+            Projection::Eac => (eac_warp(xf / size) * size, eac_warp(yf / size) * size),
+        };
+        face_direction(side, xf, yf, size)
+    };
+    (padded, direction)
+}
+
+/// same as `convert`, but each texel is sampled with `sampler` instead of `interpolation`, the
+/// fill color and the footprint of area and trilinear interpolation aren't used
+pub fn convert_with_sampler<S>(
+    config: &Config,
+    img: &DynamicImage,
+    sampler: &S,
+) -> Vec<(ImageBufferData, Side)>
+where
+    S: Sampler + Sync + ?Sized,
+{
+    let progress = AtomicU64::new(0);
+    config
+        .faces
+        .par_iter()
+        .map(|side| {
+            let (padded, direction) = side_direction(config, *side);
+            let uv_at = uv_mapping(config, img.dimensions(), direction);
+            let in_crop = crop_test(config, img.dimensions());
+            let face = render_pixels(
+                config,
+                (padded, padded),
+                |xf, yf| {
+                    let uv = uv_at(xf, yf);
+                    if in_crop(uv) {
+                        sampler.sample(img, uv)
+                    } else {
+                        fill_pixel(config)
+                    }
+                },
+                adaptive_aa(config, img.dimensions(), &uv_at),
+                &progress,
+            );
+            (face, *side)
+        })
+        .collect()
+}
+
+/// `--fill-color` in the range of the pixel type, float faces of `--linearize` are linear light
+fn fill_pixel<P: SamplePixel>(config: &Config) -> P {
+    let linear = config.samples_linear() && !config.format.is_hdr() && P::CHANNEL_MAX == 1.0;
+    let mut channels = config.fill_color.0.map(|c| c as f32 / u8::MAX as f32);
+    if linear {
+        for c in &mut channels[..3] {
+            *c = srgb_to_linear(*c);
+        }
+    }
+    P::from_f32(channels.map(|c| c * P::CHANNEL_MAX))
+}
+
+/// the `rgb` (0 to 1) of `--debug` as an opaque pixel, linear faces of `--linearize` get the
+/// values the srgb encoding turns back into `rgb`
+fn debug_pixel<P: SamplePixel>(config: &Config, [r, g, b]: [f32; 3]) -> P {
+    let encoded = config.linearize
+        && config.color_space == ColorSpace::Srgb
+        && !config.format.is_hdr()
+        && P::CHANNEL_MAX == 1.0;
+    let mut channels = [r, g, b, 1.0];
+    if encoded {
+        for c in &mut channels[..3] {
+            *c = srgb_to_linear(*c);
+        }
+    }
+    P::from_f32(channels.map(|c| c * P::CHANNEL_MAX))
+}
+
+/// pixels below which rows aren't split into more parallel tasks
+const MIN_PIXELS_PER_TASK: usize = 16 * 1024;
+
+/// map a texel coordinate (not normalized) through `direction`, the view rotation, the pole axis,
+/// the center longitude, the input projection and `--invert-v` to the uv it samples in a `width` x `height`
+/// input image
+fn uv_mapping(
+    config: &Config,
+    (width, height): (u32, u32),
+    direction: impl Fn(f32, f32) -> Vector3 + Sync,
+) -> impl Fn(f32, f32) -> (f32, f32) + Sync {
+    let rotation = rotation_matrix(
+        config.yaw.to_radians(),
+        config.pitch.to_radians(),
+        config.roll.to_radians(),
+    );
+    let rotation = match config.convention {
+        Some(convention) => matrix_product(&rotation, &convention.sampling_matrix()),
+        None => rotation,
+    };
+    let pole_axis = config.pole_axis;
+    let center_longitude = config.center_longitude.to_radians();
+    // a partial panorama stretches less of the latitude over v, so v is past 1 below it
+    let v_scale = 180.0 / config.vertical_fov;
+    let projection = config.input_projection;
+    let invert_v = config.invert_v;
+    let lens_fov = config.fisheye_fov.to_radians();
+    let aspect = height as f32 / width.max(1) as f32;
+    move |xf: f32, yf: f32| {
+        let pos = direction(xf, yf).rotate(&rotation);
+        let spr = SphericalAngle::from_normalized_vector_with_pole(pos.normalize_fast(), pole_axis);
+        let (u, v) = if projection == InputProjection::Fisheye {
+            spr.to_fisheye_uv(aspect, center_longitude, lens_fov)
+        } else if projection != InputProjection::Equirect {
+            spr.to_projected_uv(projection, aspect, center_longitude)
+        } else if center_longitude == 0.0 {
+            spr.to_uv()
+        } else {
+            spr.to_uv_with_offset(center_longitude)
+        };
+        let v = if v_scale == 1.0 { v } else { v * v_scale };
+        if invert_v {
+            (u, 1.0 - v)
+        } else {
+            (u, v)
+        }
+    }
+}
+
+/// whether a uv from `uv_mapping` is inside `--crop` of a `width` x `height` input image, the
+/// crop's angles go through the same projection as the sampled directions so the check is in uv
+fn crop_test(config: &Config, (width, height): (u32, u32)) -> impl Fn((f32, f32)) -> bool + Sync {
+    let bounds = config.crop.map(|crop| {
+        let aspect = height as f32 / width.max(1) as f32;
+        let v_scale = 180.0 / config.vertical_fov;
+        let uv = |lon: f32, lat: f32| {
+            // latitude is up and phi is down
+            let angle = SphericalAngle {
+                theta: lon.to_radians(),
+                phi: -lat.to_radians(),
+            };
+            let (u, v) = angle.to_projected_uv(config.input_projection, aspect, 0.0);
+            (u, v * v_scale)
+        };
+        let (u0, v1) = uv(crop.lon.0, crop.lat.0);
+        let (u1, v0) = uv(crop.lon.1, crop.lat.1);
+        // u of the offset is in [0, 1), so +180 wraps to 0
+        let u1 = if crop.lon.1 == 180.0 { 1.0 } else { u1 };
+        if config.invert_v {
+            (u0, u1, 1.0 - v1, 1.0 - v0)
+        } else {
+            (u0, u1, v0, v1)
+        }
+    });
+    move |(u, v)| match bounds {
+        None => true,
+        Some((u0, u1, v0, v1)) => {
+            let in_lon = if u0 <= u1 {
+                (u0..=u1).contains(&u)
+            } else {
+                u >= u0 || u <= u1
+            };
+            in_lon && (v0..=v1).contains(&v)
+        }
+    }
+}
+
+/// render a width x height image, `direction` maps a texel coordinate (not normalized) to the
+/// direction it samples before the view rotation
+fn render_rect<I>(
+    config: &Config,
+    img: &I,
+    mips: &[FaceBuffer<I::Pixel>],
+    (width, height): (u32, u32),
+    direction: impl Fn(f32, f32) -> Vector3 + Sync,
+    progress: &AtomicU64,
+) -> FaceBuffer<I::Pixel>
+where
+    I: GenericImageView + Sync,
+    I::Pixel: SamplePixel,
+{
+    let interpolation = &config.interpolation;
+    let fill = fill_pixel(config);
+    let uv_at = uv_mapping(config, img.dimensions(), &direction);
+    let in_crop = crop_test(config, img.dimensions());
+    let addressing = Addressing {
+        u: config.wrap,
+        v: config.wrap_v,
+    };
+    let sample_at = |xf: f32, yf: f32| {
+        let uv = match config.debug {
+            DebugMode::Off | DebugMode::Seams => uv_at(xf, yf),
+            DebugMode::Uv => {
+                let (u, v) = uv_at(xf, yf);
+                return debug_pixel(config, [u, v, 0.0]);
+            }
+            DebugMode::Normals => {
+                let dir = direction(xf, yf).normalize();
+                return debug_pixel(config, [dir.x, dir.y, dir.z].map(|c| c * 0.5 + 0.5));
+            }
+        };
+        if !in_crop(uv) {
+            fill
+        } else if let Interpolation::Area | Interpolation::Trilinear = interpolation {
+            let grid = (config.samples as f32).sqrt().round();
+            let footprint = uv_footprint(&uv_at, xf, yf, 1.0 / grid);
+            interpolation
+                .try_sample_mips(img, mips, uv, footprint, addressing)
+                .unwrap_or(fill)
+        } else {
+            interpolation
+                .try_sample_addressed(img, uv, addressing)
+                .unwrap_or(fill)
+        }
+    };
+    // one sample per pixel of a filter without a footprint goes a row at a time
+    if config.samples == 1
+        && matches!(config.debug, DebugMode::Off | DebugMode::Seams)
+        && !matches!(
+            interpolation,
+            Interpolation::Area | Interpolation::Trilinear
+        )
+    {
+        return render_rows(
+            (width, height),
+            &uv_at,
+            |uvs, samples| interpolation.try_sample_batch(img, uvs, addressing, samples),
+            |uv, sample| match sample {
+                Some(sample) if in_crop(uv) => sample,
+                _ => fill,
+            },
+            progress,
+        );
+    }
+    let needs_aa = adaptive_aa(config, img.dimensions(), &uv_at);
+    render_pixels(config, (width, height), sample_at, needs_aa, progress)
+}
+
+/// same as `render_pixels` with one sample per pixel, the uvs of each row are sampled together
+/// by `sample_row` and `finish` turns each uv and its sample into the pixel
+fn render_rows<P: SamplePixel>(
+    (width, height): (u32, u32),
+    uv_at: impl Fn(f32, f32) -> (f32, f32) + Sync,
+    sample_row: impl Fn(&[(f32, f32)], &mut [Option<P>]) + Sync,
+    finish: impl Fn((f32, f32), Option<P>) -> P + Sync,
+    progress: &AtomicU64,
+) -> FaceBuffer<P> {
+    let channels = P::CHANNEL_COUNT as usize;
+    let mut rect = ImageBuffer::new(width, height);
+    let min_rows = (MIN_PIXELS_PER_TASK / width.max(1) as usize).max(1);
+    rect.par_chunks_mut(width as usize * channels)
+        .with_min_len(min_rows)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let yf = y as f32;
+            let uvs: Vec<_> = (0..width).map(|x| uv_at(x as f32, yf)).collect();
+            let mut samples = vec![None; uvs.len()];
+            sample_row(&uvs, &mut samples);
+            for ((texel, uv), sample) in row.chunks_exact_mut(channels).zip(uvs).zip(samples) {
+                texel.copy_from_slice(finish(uv, sample).channels());
+            }
+            progress.fetch_add(width as u64, Ordering::Relaxed);
+        });
+    rect
+}
+
+/// uv distance between the texel coordinates `step / 2` left and right of (xf, yf) and between
+/// those above and below it, centered so mirrored texels get the same footprint, u wraps around
+fn uv_footprint(uv_at: impl Fn(f32, f32) -> (f32, f32), xf: f32, yf: f32, step: f32) -> (f32, f32) {
+    let half = step / 2.0;
+    let (ul, vl) = uv_at(xf - half, yf);
+    let (ur, vr) = uv_at(xf + half, yf);
+    let (ua, va) = uv_at(xf, yf - half);
+    let (ub, vb) = uv_at(xf, yf + half);
+    let du = |a: f32, b: f32| {
+        let d = (a - b).abs();
+        d.min(1.0 - d)
+    };
+    (
+        du(ul, ur).max(du(ua, ub)),
+        (vr - vl).abs().max((vb - va).abs()),
+    )
+}
+
+/// whether the texel at (xf, yf) gets the `--samples` sub-samples, with `--adaptive-aa` only
+/// if a neighboring pixel maps more than a texel of the `width` x `height` input away
+fn adaptive_aa<'a>(
+    config: &Config,
+    (width, height): (u32, u32),
+    uv_at: &'a (impl Fn(f32, f32) -> (f32, f32) + Sync),
+) -> impl Fn(f32, f32) -> bool + Sync + 'a {
+    let adaptive = config.adaptive_aa;
+    move |xf, yf| {
+        if !adaptive {
+            return true;
+        }
+        let (du, dv) = uv_footprint(uv_at, xf, yf, 1.0);
+        du * width as f32 > 1.0 || dv * height as f32 > 1.0
+    }
+}
+
+/// fill a width x height image with `sample_at` of each texel coordinate, averaged over the
+/// `--samples` sub-samples where `needs_aa` is true
+fn render_pixels<P: SamplePixel>(
+    config: &Config,
+    (width, height): (u32, u32),
+    sample_at: impl Fn(f32, f32) -> P + Sync,
+    needs_aa: impl Fn(f32, f32) -> bool + Sync,
+    progress: &AtomicU64,
+) -> FaceBuffer<P> {
+    // sub-samples are spread on a grid x grid pattern inside each texel
+    let grid = (config.samples as f32).sqrt().round() as u32;
+    let channels = P::CHANNEL_COUNT as usize;
+    let mut rect = ImageBuffer::new(width, height);
+    // rows are rendered in parallel so one large face uses all cores, small images are
+    // split into fewer tasks to keep the scheduling overhead low
+    let min_rows = (MIN_PIXELS_PER_TASK / width.max(1) as usize).max(1);
+    rect.par_chunks_mut(width as usize * channels)
+        .with_min_len(min_rows)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let yf = y as f32;
+            for (x, texel) in row.chunks_exact_mut(channels).enumerate() {
+                let xf = x as f32;
+                let p = if grid == 1 || !needs_aa(xf, yf) {
+                    sample_at(xf, yf)
+                } else {
+                    let mut sum = [0.0; 4];
+                    for i in 0..grid {
+                        let dx = (i as f32 + 0.5) / grid as f32 - 0.5;
+                        for j in 0..grid {
+                            let dy = (j as f32 + 0.5) / grid as f32 - 0.5;
+                            let c = sample_at(xf + dx, yf + dy).to_f32();
+                            for (s, c) in sum.iter_mut().zip(c) {
+                                *s += c;
+                            }
+                        }
+                    }
+                    let n = (grid * grid) as f32;
+                    P::from_f32(sum.map(|s| s / n))
+                };
+                texel.copy_from_slice(p.channels());
+            }
+            progress.fetch_add(width as u64, Ordering::Relaxed);
+        });
+    rect
+}
+
+/// render the stereographic "little planet" image of `--projection littleplanet`, the nadir is
+/// in the center and the front of the panorama is up, `--planet-zoom` narrows the field of view
+pub fn little_planet<I>(config: &Config, img: &I, progress: &AtomicU64) -> FaceBuffer<I::Pixel>
+where
+    I: GenericImageView + Sync,
+    I::Pixel: SamplePixel,
+{
+    let size_int = face_size(config, Side::Front);
+    let size = size_int as f32;
+    // half width of the projection plane, which touches the sphere at the nadir
+    let extent = 2.0 / config.planet_zoom;
+    let direction = |xf: f32, yf: f32| {
+        let a = ((xf + 0.5) / size * 2.0 - 1.0) * extent;
+        let b = ((yf + 0.5) / size * 2.0 - 1.0) * extent;
+        // inverse stereographic projection from the zenith, z is down
+        let r2 = a * a + b * b;
+        Vector3::new(-2.0 * b, 2.0 * a, 1.0 - r2).scale(1.0 / (1.0 + r2))
+    };
+    let mips = mips_for(config, img);
+    render_rect(
+        config,
+        img,
+        &mips,
+        (size_int, size_int),
+        direction,
+        progress,
+    )
+}
+
+/// render the pinhole camera view of `--projection view`, `--width` x `--height` pixels with a
+/// horizontal field of view of `--fov` degrees, looking at the front turned by yaw, pitch and roll
+pub fn perspective_view<I>(config: &Config, img: &I, progress: &AtomicU64) -> FaceBuffer<I::Pixel>
+where
+    I: GenericImageView + Sync,
+    I::Pixel: SamplePixel,
+{
+    let (width, height) = (config.width as f32, config.height as f32);
+    // distance of the image plane in px
+    let focal = width / 2.0 / (config.fov.to_radians() / 2.0).tan();
+    let direction =
+        |xf: f32, yf: f32| Vector3::new(focal, xf + 0.5 - width / 2.0, yf + 0.5 - height / 2.0);
+    render_rect(
+        config,
+        img,
+        &mips_for(config, img),
+        (config.width, config.height),
+        direction,
+        progress,
+    )
+}
+
+/// parse the `--samples` option, only square numbers up to 16 are allowed
+fn parse_samples(value: &str) -> std::result::Result<u32, String> {
+    match value.parse() {
+        Ok(n @ (1 | 4 | 9 | 16)) => Ok(n),
+        _ => Err(format!("{} isn't one of 1, 4, 9, 16", value)),
+    }
+}
+
+/// 8-bit rgb view of a face buffer composited over the rgb of a matte color
+struct RgbView<'a, P: Pixel>(&'a FaceBuffer<P>, FillColor);
+impl<P: SamplePixel> GenericImageView for RgbView<'_, P> {
+    type Pixel = Rgb<u8>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.0.dimensions()
+    }
+    fn get_pixel(&self, x: u32, y: u32) -> Rgb<u8> {
+        let [r, g, b, a] = self.0.get_pixel(x, y).to_f32().map(|c| c / P::CHANNEL_MAX);
+        let a = a.clamp(0.0, 1.0);
+        let [mr, mg, mb, _] = self.1 .0.map(|c| c as f32 / u8::MAX as f32);
+        Rgb([(r, mr), (g, mg), (b, mb)]
+            .map(|(c, m)| ((c * a + m * (1.0 - a)) * 255.0).round().clamp(0.0, 255.0) as u8))
+    }
+}
+
+thread_local! {
+    /// rgb pixels handed to the radiance encoder, reused by the faces saved on the same thread
+    static HDR_SCRATCH: RefCell<Vec<Rgb<f32>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// encode an image in the given format, with the icc profile of the source unless
+/// `--strip-metadata` is passed
+pub fn write_image<P, W>(
+    config: &Config,
+    img: &FaceBuffer<P>,
+    writer: &mut W,
+    format: OutputFormat,
+) -> ImageResult<()>
+where
+    P: SamplePixel + PixelWithColorType,
+    [P::Subpixel]: EncodableLayout,
+    W: Write + Seek,
+{
+    // the profile describes the srgb encoded input, it's wrong for linear faces
+    let icc = config.icc_profile.as_deref().filter(|_| {
+        !config.strip_metadata && format.supports_icc() && config.color_space == ColorSpace::Srgb
+    });
+    // the png spec doesn't allow `sRGB` next to an `iCCP` chunk, which wins anyway
+    let tag = format == OutputFormat::Png && icc.is_none();
+    if icc.is_none() && !tag {
+        return encode_image(config, img, writer, format);
+    }
+    let mut bytes = Cursor::new(Vec::new());
+    encode_image(config, img, &mut bytes, format)?;
+    let encoded = match icc {
+        Some(icc) => embed_icc_profile(bytes.into_inner(), format, icc),
+        None => tag_png_color_space(bytes.into_inner(), config.color_space),
+    };
+    writer.write_all(&encoded)?;
+    Ok(())
+}
+
+fn encode_image<P, W>(
+    config: &Config,
+    img: &FaceBuffer<P>,
+    writer: &mut W,
+    format: OutputFormat,
+) -> ImageResult<()>
+where
+    P: SamplePixel + PixelWithColorType,
+    [P::Subpixel]: EncodableLayout,
+    W: Write + Seek,
+{
+    match format {
+        // encodes from the face buffer directly, composited over `--matte` while reading the
+        // pixels since the jpeg encoder writes broken images for rgba pixels
+        OutputFormat::Jpg => JpegEncoder::new_with_quality(writer, config.quality)
+            .encode_image(&RgbView(img, config.matte)),
+        // the radiance encoder only takes rgb float pixels, alpha is dropped
+        OutputFormat::Hdr => HDR_SCRATCH.with_borrow_mut(|data| {
+            data.clear();
+            data.extend(img.pixels().map(|p| {
+                let [r, g, b, _] = p.to_f32().map(|c| c / P::CHANNEL_MAX);
+                Rgb([r, g, b])
+            }));
+            HdrEncoder::new(writer).encode(data, img.width() as usize, img.height() as usize)
+        }),
+        // the tiff encoder of the image crate doesn't take float pixels
+        OutputFormat::Tiff => {
+            let data: Vec<f32> = img
+                .pixels()
+                .flat_map(|p| p.to_f32().map(|c| c / P::CHANNEL_MAX))
+                .collect();
+            tiff::encoder::TiffEncoder::new(writer)
+                .and_then(|mut tiff| {
+                    tiff.write_image::<tiff::encoder::colortype::RGBA32Float>(
+                        img.width(),
+                        img.height(),
+                        &data,
+                    )
+                })
+                .map_err(|e| {
+                    ImageError::Encoding(EncodingError::new(
+                        ImageFormatHint::Exact(image::ImageFormat::Tiff),
+                        e,
+                    ))
+                })
+        }
+        // the encoder of the image crate only writes lossless webp, so faces are pixel exact
+        OutputFormat::Webp => WebPEncoder::new_lossless(writer).write_image(
+            img.as_raw().as_bytes(),
+            img.width(),
+            img.height(),
+            P::COLOR_TYPE,
+        ),
+        _ => img.write_to(writer, format.try_into()?),
+    }
+}
+
+/// create the file an output is saved in, with `--no-clobber` it's an error if it exists
+pub fn create_output_file(config: &Config, path: &Path) -> std::io::Result<File> {
+    if !config.no_clobber {
+        return File::create(path);
+    }
+    File::options()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => std::io::Error::new(
+                e.kind(),
+                format!(r#""{}" already exists (--no-clobber)"#, path.display()),
+            ),
+            _ => e,
+        })
+}
+
+/// save an image in the given format
+pub fn save_image<P>(
+    config: &Config,
+    img: &FaceBuffer<P>,
+    path: &Path,
+    format: OutputFormat,
+) -> ImageResult<()>
+where
+    P: SamplePixel + PixelWithColorType,
+    [P::Subpixel]: EncodableLayout,
+{
+    let mut writer = BufWriter::new(create_output_file(config, path)?);
+    write_image(config, img, &mut writer, format)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// convert an encoded equirect image to encoded cubemaps in memory, following the same
+/// steps as the command line (tone mapping, bit depth, rotate...) except packed layouts
+pub fn convert_bytes(input: &[u8], config: &Config) -> Result<Vec<(Vec<u8>, Side)>> {
+    let (img, icc) = decode_input(config, image::io::Reader::new(Cursor::new(input)))?;
+    convert_decoded(to_samplable(img), icc, config)
+}
+
+/// `convert_bytes` of an image already decoded by `to_samplable`, with its icc profile
+fn convert_decoded(
+    mut img: DynamicImage,
+    icc: Option<Vec<u8>>,
+    config: &Config,
+) -> Result<Vec<(Vec<u8>, Side)>> {
+    let config = &Config {
+        icc_profile: config.icc_profile.clone().or(icc),
+        ..config.clone()
+    };
+    let (width, height) = (img.width(), img.height());
+    if config.allow_non_2to1 && validate_panorama(config, width, height).is_err() {
+        let height = panorama_height(config, width);
+        img = img.resize_exact(width, height, image::imageops::FilterType::Triangle);
+    } else {
+        validate_panorama(config, width, height)?;
+    }
+    img = limit_input_size(config, img);
+    if config.format.mixes_hdr() {
+        bail!("Per side formats should all be hdr formats (exr, hdr, tiff) or all not.");
+    }
+    if !config.format.is_hdr() {
+        img = tone_map(config, img);
+    }
+    let sixteen = config.bit_depth == BitDepth::Sixteen && config.format.supports_16_bit();
+    if config.scalar {
+        let faces = orient_faces(config, convert_view(config, &scalar::to_scalar(&img)));
+        faces
+            .par_iter()
+            .map(|(face, side)| {
+                let mut bytes = Cursor::new(Vec::new());
+                scalar::write_scalar_image(face, &mut bytes, config.format.of(*side))?;
+                Ok((bytes.into_inner(), *side))
+            })
+            .collect()
+    } else if config.format.is_hdr() {
+        encode_faces(config, convert_view(config, &img.into_rgba32f()))
+    } else if config.samples_linear() {
+        let faces = convert_view(config, &linearize(&img));
+        if sixteen {
+            encode_faces(config, delinearize_faces::<Rgba<u16>>(config, faces))
+        } else {
+            encode_faces(config, delinearize_faces::<Rgba<u8>>(config, faces))
+        }
+    } else if sixteen {
+        encode_faces(config, convert_view(config, &img.into_rgba16()))
+    } else {
+        encode_faces(config, convert_view(config, &img))
+    }
+}
+
+/// decoded images `batch_convert` keeps, enough for a few inputs shared by its configs
+const BATCH_CACHE_SIZE: usize = 4;
+
+/// run several conversions (sizes, formats...) in parallel and save their faces into each
+/// config's output directory like the separate layout, inputs shared by configs are decoded
+/// once and kept in an `ImageCache`
+pub fn batch_convert(configs: &[Config]) -> Result<()> {
+    for config in configs {
+        if config.reads_stdin() || config.writes_stdout() {
+            bail!("Batch conversions need input and output paths.");
+        }
+        if config.layout != Layout::Separate
+            || config.format.is_cubemap_container()
+            || config.tiles > 1
+            || config.mips
+        {
+            bail!("Batch conversions only save the separate layout without tiles or mips.");
+        }
+    }
+    let cache = Mutex::new(cache::ImageCache::new(BATCH_CACHE_SIZE));
+    configs.par_iter().try_for_each(|config| {
+        // the cache is only locked for the lookup, configs sharing an input wait on its entry
+        // while the first of them decodes it
+        let entry = cache
+            .lock()
+            .expect("a conversion panicked")
+            .entry(&config.input, config)?;
+        let decoded = entry.decoded(&config.input, config)?;
+        let (img, icc) = &*decoded;
+        let faces = convert_decoded(img.clone(), icc.clone(), config)?;
+        std::fs::create_dir_all(&config.output)?;
+        for (bytes, side) in faces {
+            let name = format!(
+                "{}.{}",
+                filename(side, config.naming),
+                config.format.of(side)
+            );
+            let path = config.output.join(name);
+            create_output_file(config, &path)?.write_all(&bytes)?;
+        }
+        Ok(())
+    })
+}
+
+fn delinearize_faces<P: SamplePixel>(
+    config: &Config,
+    faces: Vec<(Rgba32FImage, Side)>,
+) -> Vec<(FaceBuffer<P>, Side)> {
+    faces
+        .into_par_iter()
+        .map(|(face, side)| (encode_color_space(config, &face), side))
+        .collect()
+}
+
+fn encode_faces<P>(
+    config: &Config,
+    faces: Vec<(FaceBuffer<P>, Side)>,
+) -> Result<Vec<(Vec<u8>, Side)>>
+where
+    P: SamplePixel + PixelWithColorType,
+    [P::Subpixel]: EncodableLayout,
+{
+    orient_faces(config, faces)
+        .par_iter()
+        .map(|(face, side)| {
+            let mut bytes = Cursor::new(Vec::new());
+            write_image(config, face, &mut bytes, config.format.of(*side))?;
+            Ok((bytes.into_inner(), *side))
+        })
+        .collect()
+}
+
+/// direction (not normalized) from the cube center to pixel (xf, yf) of a side
+///
+/// x is front, y is right (the panorama's longitude grows along it) and z is down, a right handed
+/// frame. Every side is seen from inside the cube unmirrored: its image right and down axes
+/// cross to the direction it faces, so longitude grows left to right on the four sides around
+/// the horizon. The top and bottom sides have the front at their right edge, `rotate` turns
+/// them for y-up renderers.
+pub fn face_direction(side: Side, xf: f32, yf: f32, size: f32) -> Vector3 {
+    use Side::*;
+    match side {
+        Front => Vector3::new(0.5, xf / size - 0.5, yf / size - 0.5),
+        Back => Vector3::new(-0.5, 0.5 - xf / size, yf / size - 0.5),
+        Left => Vector3::new(-(xf / size - 0.5), 0.5, yf / size - 0.5),
+        Right => Vector3::new(xf / size - 0.5, -0.5, yf / size - 0.5),
+        Top => Vector3::new(xf / size - 0.5, 0.5 - yf / size, -0.5),
+        Bottom => Vector3::new(xf / size - 0.5, yf / size - 0.5, 0.5),
+    }
+}
+
+/// the sides, in the order of `Side::all`, a view cone of `fov` degrees looking `yaw` degrees
+/// toward the right of the front and `pitch` degrees up (the yaw and pitch of
+/// `rotation_matrix`) reaches into, sides the cone only touches are left out
+pub fn visible_faces(yaw: f32, pitch: f32, fov: f32) -> Vec<Side> {
+    let rotation = rotation_matrix(yaw.to_radians(), pitch.to_radians(), 0.0);
+    let view = Vector3::new(1.0, 0.0, 0.0).rotate(&rotation).normalize();
+    let half_angle = (fov / 2.0).to_radians();
+    Side::all()
+        .into_iter()
+        .filter(|side| {
+            // the side the view is in, and a margin for the rounding of the view direction so
+            // cones that only touch a side like a pitch of 90 don't pick it up
+            let angle = angle_to_face(view, *side);
+            angle == 0.0 || angle < half_angle - 1e-4
+        })
+        .collect()
+}
+
+/// the smallest angle (radians) between the unit vector `dir` and the directions of a face,
+/// 0 inside it, otherwise the distance to the closest of its edges (arcs of great circles)
+fn angle_to_face(dir: Vector3, side: Side) -> f32 {
+    let axis = face_direction(side, 0.5, 0.5, 1.0).scale(2.0);
+    if dir.dot(&axis) >= dir.x.abs().max(dir.y.abs()).max(dir.z.abs()) {
+        return 0.0;
+    }
+    let angle = |a: &Vector3, b: &Vector3| a.dot(b).clamp(-1.0, 1.0).acos();
+    let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]
+        .map(|(x, y)| face_direction(side, x, y, 1.0).normalize());
+    let mut closest = f32::INFINITY;
+    for i in 0..4 {
+        let (a, b) = (corners[i], corners[(i + 1) % 4]);
+        let normal = a.cross(&b).normalize();
+        // the closest point of the great circle, on the edge if it's between the corners
+        let projected = dir.sub(&normal.scale(dir.dot(&normal)));
+        let on_edge = projected.len() > f32::EPSILON
+            && a.cross(&projected).dot(&normal) >= 0.0
+            && projected.cross(&b).dot(&normal) >= 0.0;
+        let edge = if on_edge {
+            angle(&dir, &projected.normalize())
+        } else {
+            angle(&dir, &a).min(angle(&dir, &b))
+        };
+        closest = closest.min(edge);
+    }
+    closest
+}
+
+/// average the border texels adjacent faces (as rendered, before `orient_faces`) share on their
+/// edges, and the three texels meeting at each corner, so a lookup without seamless filtering
+/// doesn't show hairline seams, edges without a neighboring face are kept, all faces need one
+/// size
+pub fn fix_cube_edges<P: SamplePixel>(faces: &mut [(FaceBuffer<P>, Side)]) {
+    let Some(size) = faces.first().map(|(img, _)| img.width()) else {
+        return;
+    };
+    let sizef = size as f32;
+    let key = |face: usize, x: u32, y: u32| {
+        (face * size as usize + y as usize) * size as usize + x as usize
+    };
+    // union find over the border texels, a texel's group is the texels across its edges
+    let mut parent: HashMap<usize, usize> = HashMap::new();
+    fn root(parent: &mut HashMap<usize, usize>, k: usize) -> usize {
+        let p = *parent.entry(k).or_insert(k);
+        if p == k {
+            return k;
+        }
+        let r = root(parent, p);
+        parent.insert(k, r);
+        r
+    }
+    let border = (0..size).flat_map(|i| [(i, 0), (i, size - 1), (0, i), (size - 1, i)]);
+    for (face, (_, side)) in faces.iter().enumerate() {
+        for (x, y) in border.clone() {
+            let outward = [
+                (x == 0, (-1.0, 0.0)),
+                (x == size - 1, (1.0, 0.0)),
+                (y == 0, (0.0, -1.0)),
+                (y == size - 1, (0.0, 1.0)),
+            ];
+            for (_, (dx, dy)) in outward.into_iter().filter(|(on_edge, _)| *on_edge) {
+                // the center of the texel a texel further out lies on the neighboring face
+                let (xf, yf) = (x as f32 + 0.5 + dx, y as f32 + 0.5 + dy);
+                let (neighbor, (u, v)) = face_uv(face_direction(*side, xf, yf, sizef));
+                let Some(other) = faces.iter().position(|(_, s)| *s == neighbor) else {
+                    continue;
+                };
+                let texel = |c: f32| ((c * sizef) as u32).min(size - 1);
+                let (a, b) = (key(face, x, y), key(other, texel(u), texel(v)));
+                let (ra, rb) = (root(&mut parent, a), root(&mut parent, b));
+                parent.insert(ra, rb);
+            }
+        }
+    }
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for k in parent.keys().copied().collect::<Vec<_>>() {
+        let r = root(&mut parent, k);
+        groups.entry(r).or_default().push(k);
+    }
+    let area = size as usize * size as usize;
+    let texel = |k: usize| {
+        (
+            k / area,
+            (k % size as usize) as u32,
+            (k % area / size as usize) as u32,
+        )
+    };
+    for members in groups.values().filter(|members| members.len() > 1) {
+        let mut sum = [0.0; 4];
+        for (face, x, y) in members.iter().map(|k| texel(*k)) {
+            let channels = faces[face].0.get_pixel(x, y).to_f32();
+            for (s, c) in sum.iter_mut().zip(channels) {
+                *s += c;
+            }
+        }
+        let average = P::from_f32(sum.map(|s| s / members.len() as f32));
+        for (face, x, y) in members.iter().map(|k| texel(*k)) {
+            faces[face].0.put_pixel(x, y, average);
+        }
+    }
+}
+
+/// the orientations of `--rotate`, which turn the faces into a z-up skybox for y-up renderers
+pub fn z_up_orientations() -> HashMap<Side, Orientation> {
+    HashMap::from([
+        (Side::Bottom, Orientation::rotated(Rotation::R180)),
+        (Side::Left, Orientation::rotated(Rotation::R180)),
+        (Side::Front, Orientation::rotated(Rotation::R270)),
+        (Side::Back, Orientation::rotated(Rotation::R90)),
+    ])
+}
+
+/// turn the faces into a z-up skybox, see `z_up_orientations`
+pub fn rotate<P>(entries: Vec<(FaceBuffer<P>, Side)>) -> Vec<(FaceBuffer<P>, Side)>
+where
+    P: Pixel + Send + Sync + 'static,
+    P::Subpixel: Send + Sync,
+{
+    orient(entries, &z_up_orientations())
+}
+
+/// turn and mirror each face by the orientation of its side, sides without one are kept
+pub fn orient<P>(
+    entries: Vec<(FaceBuffer<P>, Side)>,
+    per_side: &HashMap<Side, Orientation>,
+) -> Vec<(FaceBuffer<P>, Side)>
+where
+    P: Pixel + Send + Sync + 'static,
+    P::Subpixel: Send + Sync,
+{
+    use image::imageops::*;
+    entries
+        .into_par_iter()
+        .map(|(img, side)| {
+            let orientation = per_side.get(&side).copied().unwrap_or_default();
+            let mut image = match orientation.rotation {
+                Rotation::None => img,
+                Rotation::R90 => rotate90(&img),
+                Rotation::R180 => rotate180(&img),
+                Rotation::R270 => rotate270(&img),
+            };
+            if orientation.flip_x {
+                flip_horizontal_in_place(&mut image);
+            }
+            if orientation.flip_y {
+                flip_vertical_in_place(&mut image);
+            }
+            (image, side)
+        })
+        .collect()
+}
+
+/// apply `--rotate`, `--orient`, `--flip-x` and `--flip-y` in that order
+pub fn orient_faces<P>(
+    config: &Config,
+    mut entries: Vec<(FaceBuffer<P>, Side)>,
+) -> Vec<(FaceBuffer<P>, Side)>
+where
+    P: Pixel + Send + Sync + 'static,
+    P::Subpixel: Send + Sync,
+{
+    if config.rotate {
+        entries = rotate(entries);
+    }
+    if config.convention.is_some() {
+        entries = orient(entries, &convention_orientations());
+    }
+    if let Some(orientations) = &config.orient {
+        entries = orient(entries, &orientations.0);
+    }
+    flip(&mut entries, config.flip_x, config.flip_y);
+    entries
+}
+
+/// mirror each face in place, left to right with `flip_x` and top to bottom with `flip_y`,
+/// applied after `rotate` so the flips are in the orientation of the saved images
+pub fn flip<P>(entries: &mut [(FaceBuffer<P>, Side)], flip_x: bool, flip_y: bool)
+where
+    P: Pixel + Send + Sync + 'static,
+    P::Subpixel: Send + Sync,
+{
+    use image::imageops::*;
+    entries.par_iter_mut().for_each(|(img, _)| {
+        if flip_x {
+            flip_horizontal_in_place(img);
+        }
+        if flip_y {
+            flip_vertical_in_place(img);
+        }
+    });
+}
+
+/// convert cubemaps (6 squared images, not rotated) back to 1 equirect image
+pub fn cubemap_to_equirect<P: SamplePixel>(
+    faces: &[(FaceBuffer<P>, Side)],
+    width: u32,
+    height: u32,
+    interpolation: &Interpolation,
+) -> FaceBuffer<P> {
+    let channels = P::CHANNEL_COUNT as usize;
+    let mut equirect = ImageBuffer::new(width, height);
+    equirect
+        .par_chunks_mut(width as usize * channels)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let v = (y as f32 + 0.5) / height as f32;
+            for x in 0..width as usize {
+                let u = (x as f32 + 0.5) / width as f32;
+                let dir = SphericalAngle::from_uv(u, v).to_direction();
+                let (side, face_uv) = face_uv(dir);
+                let p = faces
+                    .iter()
+                    .find(|(_, s)| *s == side)
+                    .map(|(img, _)| interpolation.sample_clamped(img, face_uv))
+                    .unwrap_or(P::FALLBACK);
+                row[x * channels..(x + 1) * channels].copy_from_slice(p.channels());
+            }
+        });
+    equirect
+}
+
+/// pick the side a direction points at by its largest-magnitude axis,
+/// and return the clamped face-local uv, inverse of `face_direction`
+fn face_uv(dir: Vector3) -> (Side, (f32, f32)) {
+    let (ax, ay, az) = (dir.x.abs(), dir.y.abs(), dir.z.abs());
+    let (side, x, y) = if ax >= ay && ax >= az {
+        // project onto the cube surface at distance 0.5
+        let p = 0.5 / ax;
+        if dir.x > 0.0 {
+            (Side::Front, dir.y * p + 0.5, dir.z * p + 0.5)
+        } else {
+            (Side::Back, 0.5 - dir.y * p, dir.z * p + 0.5)
+        }
+    } else if ay >= az {
+        let p = 0.5 / ay;
+        if dir.y > 0.0 {
+            (Side::Left, 0.5 - dir.x * p, dir.z * p + 0.5)
+        } else {
+            (Side::Right, dir.x * p + 0.5, dir.z * p + 0.5)
+        }
+    } else {
+        let p = 0.5 / az;
+        if dir.z < 0.0 {
+            (Side::Top, dir.x * p + 0.5, 0.5 - dir.y * p)
+        } else {
+            (Side::Bottom, dir.x * p + 0.5, dir.y * p + 0.5)
+        }
+    };
+    (side, (x.clamp(0.0, 1.0), y.clamp(0.0, 1.0)))
+}
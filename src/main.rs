@@ -1,182 +1,1249 @@
-use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
-use rayon::prelude::*;
+use std::cell::RefCell;
+use std::ffi::OsString;
 use std::fs::create_dir_all;
-use std::{fmt::Display, path::PathBuf};
+use std::io::{BufWriter, Cursor, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
 
-mod math;
 use anyhow::{Ok, Result};
-use math::{Interpolation, SphericalAngle, Vector3};
+use equirect2cubemap::config_file;
+use equirect2cubemap::contact_sheet::{contact_sheet, thumbnail, THUMBNAIL_SIZE};
+use equirect2cubemap::dds::write_dds_cubemap;
+use equirect2cubemap::ktx2::{write_ktx2_cubemap, KTX2_PREFIX_SIZE};
+use equirect2cubemap::layout::{
+    generate_mip_chain, mip_sizes, pack_horizontal_cross, pack_row, tile_image,
+    validate_face_order, validate_faces, validate_tiles, Layout,
+};
+use equirect2cubemap::manifest::{json_string, Manifest, ManifestFace};
+use equirect2cubemap::math::{
+    face_stats, luminance_histogram, InputProjection, SamplePixel, ToneMapping,
+    HISTOGRAM_LOG2_RANGE,
+};
+use equirect2cubemap::scalar::{to_scalar, write_scalar_image, ScalarImage};
+use equirect2cubemap::sha256::{to_hex, Sha256};
+use equirect2cubemap::{
+    convert_each_with_progress, convert_view_with_progress, create_output_file,
+    cubemap_to_equirect, decode_input, encode_color_space, expand_output_path, face_size, filename,
+    limit_input_size, limited_input_size, linearize, little_planet, load_cubemap_dir, orient_faces,
+    panorama_height, perspective_view, premultiply_alpha, save_image, to_samplable, tone_map,
+    validate_panorama, visible_faces, write_image, BitDepth, Config, FaceBuffer, OutputFormat,
+    Projection, Side,
+};
+use image::{
+    DynamicImage, EncodableLayout, GenericImageView, ImageFormat, PixelWithColorType, Rgba,
+    RgbaImage,
+};
+use rayon::prelude::*;
+
+/// print progress to stdout, or to stderr when stdout carries the output image
+macro_rules! report {
+    ($config:expr, $($arg:tt)*) => {
+        if $config.quiet {
+            // silenced
+        } else if $config.writes_stdout() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// the files saved so far, listed by `--json`
+static WRITTEN: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// set by the first ctrl-c, checked before an image is started and before anything is saved, so
+/// a conversion still finishes rendering its faces but only the files already being written are
+/// saved
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// exit status after a ctrl-c, 128 + SIGINT like shells report it
+const INTERRUPTED_EXIT_CODE: i32 = 130;
 
-type ImageBufferData = ImageBuffer<Rgba<u8>, Vec<u8>>;
+/// catch the first ctrl-c so the faces being saved are completed and the remaining ones and
+/// images are skipped, no file is left half written, a second ctrl-c exits at once
+#[cfg(unix)]
+fn handle_interrupts() {
+    extern "C" fn on_interrupt(_: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESETHAND;
+        libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+    }
+}
+
+/// other platforms keep the default ctrl-c, which exits at once
+#[cfg(not(unix))]
+fn handle_interrupts() {}
+
+/// fails once ctrl-c was pressed, checked before starting an image and before saving a face,
+/// packed image, cubemap or equirect image
+fn check_interrupted() -> Result<()> {
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        anyhow::bail!("Interrupted, the files being saved were completed and the rest skipped.");
+    }
+    Ok(())
+}
+
+/// exit with `INTERRUPTED_EXIT_CODE` once ctrl-c was pressed, even if the conversion got to
+/// finish before noticing it
+fn exit_if_interrupted() {
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+}
+
+thread_local! {
+    /// durations of the stages of the image converted on this thread, for `--timing-json`
+    static TIMINGS: RefCell<Vec<(&'static str, Duration)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// print how long a stage took with `--verbose` and keep it for `--timing-json`
+fn report_timing(config: &Config, stage: &'static str, elapsed: Duration) {
+    if config.verbose {
+        report!(config, "{}: {:?}", stage, elapsed);
+    }
+    if config.timing_json {
+        TIMINGS.with_borrow_mut(|timings| timings.push((stage, elapsed)));
+    }
+}
+
+/// print the stages timed on this thread as one json object to stderr with `--timing-json`,
+/// durations are in seconds
+fn print_timing_json(config: &Config) {
+    if !config.timing_json {
+        return;
+    }
+    let stages: Vec<String> = TIMINGS
+        .with_borrow_mut(std::mem::take)
+        .into_iter()
+        .map(|(stage, elapsed)| {
+            format!(
+                r#""{}": {}"#,
+                stage.to_lowercase().replace(' ', "_"),
+                elapsed.as_secs_f64()
+            )
+        })
+        .collect();
+    eprintln!(
+        r#"{{"input": {}, {}}}"#,
+        json_string(&config.input.to_string_lossy()),
+        stages.join(", ")
+    );
+}
+
+/// print an error as the one json object of `--json`, command line errors without the tips and
+/// usage clap adds after them
+fn print_json_error(e: &anyhow::Error) {
+    let message = match e.downcast_ref::<clap::Error>() {
+        Some(e) => {
+            let rendered = e.to_string();
+            let error = rendered.split("\n\n").next().unwrap_or_default();
+            let words: Vec<_> = error.split_whitespace().collect();
+            words.join(" ").trim_start_matches("error: ").to_owned()
+        }
+        None => format!("{:#}", e),
+    };
+    println!(r#"{{"error": {}}}"#, json_string(&message));
+}
 
 fn main() -> Result<()> {
     use clap::Parser;
-    let config = Config::parse();
-    let path = &config.input;
+    // `--json` is looked for before parsing so a bad command line or config file is reported
+    // as json as well
+    let has_json = |args: &[OsString]| {
+        args.iter()
+            .skip(1)
+            .take_while(|arg| *arg != "--")
+            .any(|arg| arg == "--json")
+    };
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let mut json = has_json(&args);
+    let config = config_file::args_with_config_file(args).and_then(|args| {
+        json |= has_json(&args);
+        Ok(Config::try_parse_from(args)?)
+    });
+    let config = match config {
+        Result::Ok(config) => config,
+        Err(e) => {
+            if let Some(e) = e.downcast_ref::<clap::Error>() {
+                // --help and --version print to stdout and aren't errors
+                if !json || !e.use_stderr() {
+                    e.exit();
+                }
+            }
+            if !json {
+                return Err(e);
+            }
+            print_json_error(&e);
+            std::process::exit(1);
+        }
+    };
+    handle_interrupts();
+    if !config.json {
+        let result = run(&config).map(|_| ());
+        if let Err(e) = &result {
+            // exiting skips the error main would print
+            if INTERRUPTED.load(Ordering::SeqCst) {
+                eprintln!("Error: {:#}", e);
+            }
+        }
+        exit_if_interrupted();
+        return result;
+    }
+    let start_time = std::time::Instant::now();
+    let config = Config {
+        quiet: true,
+        ..config
+    };
+    match run(&config) {
+        Result::Ok(output) => {
+            let files: Vec<String> = WRITTEN
+                .lock()
+                .expect("a conversion panicked")
+                .iter()
+                .map(|path| {
+                    let bytes = std::fs::metadata(path).map_or(0, |m| m.len());
+                    format!(
+                        r#"{{"path": {}, "bytes": {}}}"#,
+                        json_string(&path.to_string_lossy()),
+                        bytes
+                    )
+                })
+                .collect();
+            println!(
+                r#"{{"output": {}, "files": [{}], "elapsed": {}}}"#,
+                json_string(&output.to_string_lossy()),
+                files.join(", "),
+                start_time.elapsed().as_secs_f64()
+            );
+            exit_if_interrupted();
+            Ok(())
+        }
+        Err(e) => {
+            print_json_error(&e);
+            exit_if_interrupted();
+            std::process::exit(1);
+        }
+    }
+}
+
+/// validate the config and convert, returns the output directory (or file of `--reverse`)
+fn run(config: &Config) -> Result<PathBuf> {
+    let mut config = config.clone();
+    if config.json
+        && (config.writes_stdout()
+            || config.dry_run
+            || config.histogram
+            || config.stats
+            || config.print_hashes)
+    {
+        anyhow::bail!(
+            "--json can't be combined with writing to stdout, --dry-run, --histogram, --stats or --print-hashes."
+        );
+    }
+    if config.reverse {
+        reverse(&config)?;
+        return Ok(config.output);
+    }
+    match config.projection {
+        Projection::LittlePlanet | Projection::View if config.format.is_cubemap_container() => {
+            anyhow::bail!(
+                "{} isn't a format for a single image.",
+                config.format.default
+            );
+        }
+        Projection::LittlePlanet if config.planet_zoom <= 0.0 => {
+            anyhow::bail!(
+                "--planet-zoom should be positive, got {}.",
+                config.planet_zoom
+            );
+        }
+        Projection::View if !(config.fov > 0.0 && config.fov < 180.0) => {
+            anyhow::bail!(
+                "--fov should be between 0 and 180 degrees, got {}.",
+                config.fov
+            );
+        }
+        Projection::View if config.width == 0 || config.height == 0 => {
+            anyhow::bail!("--width and --height should be positive.");
+        }
+        _ => {}
+    }
+    if !(config.vertical_fov > 0.0 && config.vertical_fov <= 180.0) {
+        anyhow::bail!(
+            "--vertical-fov should be between 0 and 180 degrees, got {}.",
+            config.vertical_fov
+        );
+    }
+    if !config.format.is_uniform() {
+        if config.layout != Layout::Separate
+            || config.format.is_cubemap_container()
+            || config.writes_stdout()
+            || !matches!(config.projection, Projection::Standard | Projection::Eac)
+        {
+            anyhow::bail!("Per side formats need the separate layout and image formats.");
+        }
+        if config.format.mixes_hdr() {
+            anyhow::bail!(
+                "Per side formats should all be hdr formats (exr, hdr, tiff) or all not."
+            );
+        }
+    }
+    if config.vertical_fov < 180.0 && config.input_projection != InputProjection::Equirect {
+        anyhow::bail!("--vertical-fov needs an equirect input image.");
+    }
+    if config.crop.is_some()
+        && matches!(
+            config.input_projection,
+            InputProjection::Mirrorball | InputProjection::Fisheye
+        )
+    {
+        anyhow::bail!("--crop needs an input image mapping latitude to its rows.");
+    }
+    if !(config.fisheye_fov > 0.0 && config.fisheye_fov <= 360.0) {
+        anyhow::bail!("--fisheye-fov should be above 0 and at most 360 degrees.");
+    }
+    if config.scalar
+        && (config.layout != Layout::Separate
+            || config.writes_stdout()
+            || config.tiles > 1
+            || !matches!(config.projection, Projection::Standard | Projection::Eac)
+            || !config
+                .format
+                .all()
+                .all(|format| matches!(format, OutputFormat::Exr | OutputFormat::Tiff)))
+    {
+        anyhow::bail!("--scalar needs exr or tiff faces of the separate layout without tiles.");
+    }
+    if config.mips
+        && (config.layout != Layout::Separate
+            || config.format.is_cubemap_container()
+            || config.writes_stdout()
+            || config.tiles > 1
+            || config.scalar
+            || !matches!(config.projection, Projection::Standard | Projection::Eac))
+    {
+        anyhow::bail!("--mips needs image faces of the separate layout without tiles or --scalar.");
+    }
+    if config.fix_edges
+        && (config.edge_pad > 0
+            || config.low_memory
+            || config.projection != Projection::Standard
+            || config.size.uniform().is_none())
+    {
+        anyhow::bail!(
+            "--fix-edges needs the standard projection and one size for all sides, without --edge-pad or --low-memory."
+        );
+    }
+    if config.rotate && config.convention.is_some() {
+        anyhow::bail!("--rotate and --convention can't be combined.");
+    }
+    if config.adaptive_aa && config.samples == 1 {
+        anyhow::bail!("--adaptive-aa needs --samples above 1.");
+    }
+    if config.writes_stdout()
+        && config.layout == Layout::Separate
+        && !config.format.is_cubemap_container()
+        && !matches!(
+            config.projection,
+            Projection::LittlePlanet | Projection::View
+        )
+    {
+        anyhow::bail!("Writing to stdout needs a packed layout (--layout cross or row).");
+    }
+    if config.writes_stdout() && config.print_hashes {
+        anyhow::bail!("Printing hashes needs an output directory.");
+    }
+    if config.writes_stdout() && config.manifest {
+        anyhow::bail!("Writing a manifest needs an output directory.");
+    }
+    if config.contact_sheet
+        && (config.writes_stdout()
+            || !matches!(config.projection, Projection::Standard | Projection::Eac))
+    {
+        anyhow::bail!("--contact-sheet needs an output directory and a cubemap projection.");
+    }
+    if config.low_memory
+        && (config.layout != Layout::Separate || config.format.is_cubemap_container())
+    {
+        anyhow::bail!("--low-memory needs the separate layout and an image format.");
+    }
+    if config.layout == Layout::Row {
+        validate_face_order(&config.face_order)?;
+    }
+    if config.faces_only_visible {
+        if !matches!(config.projection, Projection::Standard | Projection::Eac) {
+            anyhow::bail!("--faces-only-visible needs the standard or eac projection.");
+        }
+        if !(config.cull_fov > 0.0 && config.cull_fov <= 360.0) {
+            anyhow::bail!("--cull-fov should be above 0 and at most 360 degrees.");
+        }
+        let visible = visible_faces(config.cull_yaw, config.cull_pitch, config.cull_fov);
+        config.faces.retain(|side| visible.contains(side));
+        if config.faces.is_empty() {
+            anyhow::bail!("None of the sides of --faces is visible from the --cull-* camera.");
+        }
+        let names: Vec<_> = config.faces.iter().map(Side::to_string).collect();
+        report!(config, "Rendering the visible sides: {}.", names.join(", "));
+    }
+    validate_faces(&config.faces)?;
+    if config.tiles > 1 {
+        if config.layout != Layout::Separate
+            || config.format.is_cubemap_container()
+            || config.writes_stdout()
+            || !matches!(config.projection, Projection::Standard | Projection::Eac)
+        {
+            anyhow::bail!("--tiles needs the separate layout and an output directory.");
+        }
+        let sizes = config
+            .faces
+            .iter()
+            .map(|side| (*side, face_size(&config, *side) + 2 * config.edge_pad));
+        validate_tiles(sizes, config.tiles)?;
+    }
+    if config.faces.len() != 6
+        && (config.layout != Layout::Separate || config.format.is_cubemap_container())
+    {
+        anyhow::bail!("Packed layouts and cubemap formats need all 6 sides.");
+    }
+    if (config.layout != Layout::Separate
+        || config.format.is_cubemap_container()
+        || config.projection == Projection::LittlePlanet)
+        && config.size.uniform().is_none()
+    {
+        anyhow::bail!(
+            "Packed layouts, cubemap formats and little planets need the same size for all sides."
+        );
+    }
+    if config.input_list.is_some() || config.input.is_dir() {
+        if config.writes_stdout() {
+            anyhow::bail!("Writing to stdout needs a single input image.");
+        }
+        let inputs = match &config.input_list {
+            Some(list) => read_input_list(list, &config.input)?,
+            None => list_directory(&config.input)?,
+        };
+        batch(&config, &inputs)?;
+        return Ok(config.output);
+    }
+    let config = Config {
+        output: expand_output_path(&config.output.to_string_lossy(), &config.input, &config),
+        ..config
+    };
+    convert_file(&config)?;
+    Ok(config.output)
+}
+
+/// the images in a directory, sorted by path
+fn list_directory(dir: &std::path::Path) -> Result<Vec<PathBuf>> {
+    let mut inputs = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && image::ImageFormat::from_path(&path).is_ok() {
+            inputs.push(path);
+        }
+    }
+    inputs.sort();
+    Ok(inputs)
+}
+
+/// the paths of `--input-list` in the order listed, relative ones joined onto `base`
+fn read_input_list(list: &std::path::Path, base: &std::path::Path) -> Result<Vec<PathBuf>> {
+    let text = std::fs::read_to_string(list)
+        .map_err(|e| anyhow::anyhow!(r#"Failed to read "{}": {}"#, list.display(), e))?;
+    let inputs: Vec<PathBuf> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base.join(line))
+        .collect();
+    // each image gets the subdirectory of its stem, two with one stem would overwrite each other
+    for (i, input) in inputs.iter().enumerate() {
+        if let Some(other) = inputs[..i]
+            .iter()
+            .find(|other| other.file_stem() == input.file_stem())
+        {
+            anyhow::bail!(
+                r#""{}" and "{}" would be saved in the same directory."#,
+                other.display(),
+                input.display()
+            );
+        }
+    }
+    Ok(inputs)
+}
+
+/// convert every input image into its own subdirectory of the output directory
+fn batch(config: &Config, inputs: &[PathBuf]) -> Result<()> {
     let start_time = std::time::Instant::now();
-    let img = image::open(path)?;
+    // each job takes the next image, conversions still share the global rayon pool
+    let next = AtomicUsize::new(0);
+    let converted = AtomicUsize::new(0);
+    let failures = AtomicUsize::new(0);
+    // dry runs print their plan instead
+    let report_each = !config.quiet && !config.json && !config.dry_run;
+    std::thread::scope(|scope| {
+        for _ in 0..config.jobs.clamp(1, inputs.len().max(1)) {
+            scope.spawn(|| {
+                while let Some(input) = inputs.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    if INTERRUPTED.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    // a templated output names the directory of each image, otherwise it's
+                    // the output directory joined with the file stem
+                    let template = config.output.to_string_lossy();
+                    let output = if template.contains("{stem}") {
+                        expand_output_path(&template, input, config)
+                    } else {
+                        let stem = input.file_stem().unwrap_or_default();
+                        expand_output_path(&template, input, config).join(stem)
+                    };
+                    let config = Config {
+                        input: input.to_path_buf(),
+                        output,
+                        quiet: true,
+                        ..config.clone()
+                    };
+                    match convert_file(&config) {
+                        Err(e) => {
+                            failures.fetch_add(1, Ordering::Relaxed);
+                            eprintln!("Failed to convert {}: {}", input.display(), e);
+                        }
+                        Result::Ok(()) => {
+                            converted.fetch_add(1, Ordering::Relaxed);
+                            if report_each {
+                                println!("Converted {}", input.display());
+                            }
+                        }
+                    }
+                }
+            });
+        }
+    });
+    let failures = failures.into_inner();
     let elapsed = start_time.elapsed();
-    println!("Read and Parse: {:?}", elapsed);
-    let width = img.width();
-    let height = img.height();
-    if width != height * 2 {
-        panic!("Image width should be exact 2 times of image height.")
+    if !config.json {
+        println!(
+            "{} {} of {} images in {:?}, {} failed.",
+            if config.dry_run {
+                "Planned"
+            } else {
+                "Converted"
+            },
+            converted.into_inner(),
+            inputs.len(),
+            elapsed,
+            failures
+        );
     }
+    check_interrupted()?;
+    if failures > 0 {
+        anyhow::bail!("{} images failed to convert.", failures);
+    }
+    Ok(())
+}
 
-    create_dir_all(&config.output)?;
+/// convert a single equirect image
+fn convert_file(config: &Config) -> Result<()> {
+    if config.dry_run {
+        return dry_run(config);
+    }
+    // stages of an earlier image that failed on this thread
+    TIMINGS.with_borrow_mut(Vec::clear);
+    let path = &config.input;
     let start_time = std::time::Instant::now();
-    // convert equirect to cubemaps
-    let mut data = convert(&config, img);
+    let (img, icc) = if config.reads_stdin() {
+        let mut bytes = Vec::new();
+        std::io::stdin().read_to_end(&mut bytes)?;
+        decode_input(config, image::io::Reader::new(Cursor::new(bytes)))?
+    } else {
+        decode_input(config, image::io::Reader::open(path)?)?
+    };
+    let mut img = to_samplable(img);
+    let config = &Config {
+        icc_profile: icc,
+        ..config.clone()
+    };
     let elapsed = start_time.elapsed();
-    println!("Convert: {:?}", elapsed);
-    if config.rotate {
+    report_timing(config, "Read and Parse", elapsed);
+    let width = img.width();
+    let height = img.height();
+    if config.allow_non_2to1 && validate_panorama(config, width, height).is_err() {
+        let start_time = std::time::Instant::now();
+        let height = panorama_height(config, width);
+        img = img.resize_exact(width, height, image::imageops::FilterType::Triangle);
+        let elapsed = start_time.elapsed();
+        if config.verbose {
+            report!(config, "Resample to {}x{}", width, height);
+        }
+        report_timing(config, "Resample", elapsed);
+    } else {
+        validate_panorama(config, width, height)?;
+    }
+    if let Some((width, height)) = limited_input_size(config, img.width(), img.height()) {
         let start_time = std::time::Instant::now();
-        data = rotate(data);
+        report!(
+            config,
+            "Downscaling the input image from {}x{} to {}x{} (--max-input-dimension {}).",
+            img.width(),
+            img.height(),
+            width,
+            height,
+            config.max_input_dimension.expect("a limit to downscale to")
+        );
+        img = limit_input_size(config, img);
         let elapsed = start_time.elapsed();
-        println!("Rotate: {:?}", elapsed);
+        report_timing(config, "Downscale", elapsed);
+    }
+    if config.histogram {
+        print_histogram(config, &img);
+    }
+    if config.tone_map_operator != ToneMapping::None && !config.format.is_hdr() {
+        let start_time = std::time::Instant::now();
+        img = tone_map(config, img);
+        let elapsed = start_time.elapsed();
+        report_timing(config, "Tone Mapping", elapsed);
+    }
+
+    let bit_depth = if config.bit_depth == BitDepth::Sixteen
+        && !config.format.supports_16_bit()
+        && !config.format.is_hdr()
+    {
+        let format = config.format.all().find(|format| !format.supports_16_bit());
+        report!(
+            config,
+            "{} doesn't support 16-bit output, falling back to 8-bit.",
+            format.expect("a format without 16-bit support")
+        );
+        BitDepth::Eight
+    } else {
+        config.bit_depth
+    };
+
+    if !config.writes_stdout() {
+        create_dir_all(&config.output)?;
     }
+    if config.scalar {
+        convert_and_save_scalar(config, &to_scalar(&img))?;
+    } else if config.format.is_hdr() {
+        convert_and_save(config, &img.into_rgba32f(), |face| face)?;
+    } else if config.samples_linear() {
+        let img = linearize(&img);
+        match bit_depth {
+            BitDepth::Eight => convert_and_save(config, &img, |face| {
+                encode_color_space::<Rgba<u8>>(config, &face)
+            })?,
+            BitDepth::Sixteen => convert_and_save(config, &img, |face| {
+                encode_color_space::<Rgba<u16>>(config, &face)
+            })?,
+        }
+    } else {
+        match bit_depth {
+            BitDepth::Eight => convert_and_save(config, &img, |face| face)?,
+            BitDepth::Sixteen => convert_and_save(config, &img.into_rgba16(), |face| face)?,
+        }
+    }
+    if config.print_hashes {
+        print_hashes(config)?;
+    }
+    if config.json {
+        let mut written = WRITTEN.lock().expect("a conversion panicked");
+        written.extend(output_plan(config).into_iter().map(|(path, ..)| path));
+        if config.manifest || config.tiles > 1 {
+            written.push(config.output.join("index.json"));
+        }
+        if config.contact_sheet {
+            written.push(config.output.join("contact_sheet.png"));
+        }
+    }
+    print_timing_json(config);
+    if !config.writes_stdout() && !config.quiet {
+        println!(
+            r#"Generated images has been saved in "{}""#,
+            config.output.display()
+        );
+    }
+    Ok(())
+}
+
+/// validate the input and print the files the conversion would write, nothing is written
+fn dry_run(config: &Config) -> Result<()> {
+    let (width, height) = if config.reads_stdin() {
+        let mut bytes = Vec::new();
+        std::io::stdin().read_to_end(&mut bytes)?;
+        image::io::Reader::new(Cursor::new(bytes))
+            .with_guessed_format()?
+            .into_dimensions()?
+    } else {
+        image::image_dimensions(&config.input)?
+    };
+    if !config.allow_non_2to1 {
+        validate_panorama(config, width, height)?;
+    }
+    let plan = output_plan(config);
+    // estimated from the uncompressed pixels, encoded files other than dds and ktx2 are usually smaller
+    let bytes_per_pixel = |format| match format {
+        _ if config.scalar => 4,
+        OutputFormat::Exr | OutputFormat::Tiff => 16,
+        OutputFormat::Jpg => 3,
+        OutputFormat::Png if config.bit_depth == BitDepth::Sixteen => 8,
+        _ => 4,
+    };
+    let mut total = 0;
+    for (path, width, height, format) in &plan {
+        let bytes = *width as u64 * *height as u64 * bytes_per_pixel(*format);
+        total += bytes;
+        println!("{} {}x{} {} bytes", path.display(), width, height, bytes);
+    }
+    if config.format.is_cubemap_container() && !plan.is_empty() {
+        // magic and header
+        total += match config.format.default {
+            OutputFormat::Ktx2 => KTX2_PREFIX_SIZE as u64,
+            _ => 128,
+        };
+    }
+    let formats = if config.format.is_uniform() {
+        config.format.default.to_string()
+    } else {
+        "several formats".to_owned()
+    };
+    println!(
+        "{} files of {} from {}x{}, about {} bytes in total",
+        plan.len(),
+        formats,
+        width,
+        height,
+        total
+    );
+    if config.manifest || config.tiles > 1 {
+        println!("{}", config.output.join("index.json").display());
+    }
+    if config.contact_sheet {
+        println!("{}", config.output.join("contact_sheet.png").display());
+    }
+    Ok(())
+}
+
+/// print the sha-256 of each saved image of `output_plan` with `--print-hashes`, read back
+/// from disk so they cover the encoded bytes
+fn print_hashes(config: &Config) -> Result<()> {
     let start_time = std::time::Instant::now();
-    let size = config.size;
+    for (path, _, _, _) in output_plan(config) {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut std::fs::File::open(&path)?, &mut hasher)?;
+        println!("{}  {}", to_hex(&hasher.finish()), path.display());
+    }
+    let elapsed = start_time.elapsed();
+    report_timing(config, "Hash", elapsed);
+    Ok(())
+}
 
-    use image::EncodableLayout as _;
-    use rayon::prelude::*;
+/// the images the conversion writes as `(path, width, height, format)`, `-` for stdout
+fn output_plan(config: &Config) -> Vec<(PathBuf, u32, u32, OutputFormat)> {
+    let entry = |name: String, width, height, format: OutputFormat| {
+        let path = if config.writes_stdout() {
+            PathBuf::from("-")
+        } else {
+            config.output.join(format!("{}.{}", name, format))
+        };
+        (path, width, height, format)
+    };
+    let default = config.format.default;
+    let padded = |side| face_size(config, side) + 2 * config.edge_pad;
+    match config.projection {
+        Projection::LittlePlanet => {
+            let size = face_size(config, Side::Front);
+            return vec![entry("planet".to_owned(), size, size, default)];
+        }
+        Projection::View => {
+            return vec![entry(
+                "view".to_owned(),
+                config.width,
+                config.height,
+                default,
+            )]
+        }
+        Projection::Standard | Projection::Eac => {}
+    }
+    if config.format.is_cubemap_container() {
+        let size = padded(Side::Front);
+        return vec![entry("cubemap".to_owned(), size, size * 6, default)];
+    }
+    match config.layout {
+        Layout::Cross => {
+            let size = padded(Side::Front);
+            vec![entry("cubemap".to_owned(), size * 4, size * 3, default)]
+        }
+        Layout::Row => {
+            let size = padded(Side::Front);
+            vec![entry("cubemap".to_owned(), size * 6, size, default)]
+        }
+        Layout::Separate => {
+            let n = config.tiles;
+            let mut plan = Vec::new();
+            for side in &config.faces {
+                let name = filename(*side, config.naming);
+                let format = config.format.of(*side);
+                let size = padded(*side);
+                if config.mips {
+                    for (level, size) in mip_sizes(size).into_iter().enumerate() {
+                        plan.push(entry(format!("{}_mip{}", name, level), size, size, format));
+                    }
+                } else if n > 1 {
+                    for y in 0..n {
+                        for x in 0..n {
+                            plan.push(entry(
+                                format!("{}_{}_{}", name, x, y),
+                                size / n,
+                                size / n,
+                                format,
+                            ));
+                        }
+                    }
+                } else {
+                    plan.push(entry(name.to_owned(), size, size, format));
+                }
+            }
+            plan
+        }
+    }
+}
 
-    // write images to disk
-    data.par_iter().for_each(|(img, side)| {
-        image::save_buffer_with_format(
-            config.output.join(format!("{}.{}", side, &config.format)),
-            img.as_bytes(),
-            size,
-            size,
-            image::ColorType::Rgba8,
-            config.format.into(),
-        )
-        .unwrap();
+/// bins of `--histogram`
+const HISTOGRAM_BINS: usize = 24;
+
+/// print the luminance histogram of the source as a bar chart, to stderr when the faces go to
+/// stdout
+fn print_histogram(config: &Config, img: &DynamicImage) {
+    let counts = luminance_histogram(img, HISTOGRAM_BINS);
+    let float = matches!(
+        img,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+    let (low, high) = if float {
+        HISTOGRAM_LOG2_RANGE
+    } else {
+        (0.0, 1.0)
+    };
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    let mut chart = String::new();
+    for (i, count) in counts.iter().enumerate() {
+        let start = low + (high - low) * i as f32 / counts.len() as f32;
+        let bar = "#".repeat((count * 50).div_ceil(max) as usize);
+        let label = if float {
+            format!("{:+6.1} EV", start)
+        } else {
+            format!("{:6.3}", start)
+        };
+        chart.push_str(&format!("{} |{:<50} {}\n", label, bar, count));
+    }
+    if config.writes_stdout() {
+        eprint!("{}", chart);
+    } else {
+        print!("{}", chart);
+    }
+}
+
+/// print the `face_stats` of a face named `name` with `--stats`, to stderr when the faces go to
+/// stdout
+fn print_stats<P: SamplePixel>(config: &Config, img: &FaceBuffer<P>, name: &str) {
+    if !config.stats {
+        return;
+    }
+    let stats = face_stats(img);
+    let line = format!(
+        "{}: min {:.3}, max {:.3}, mean {:.3}, {:.2}% clipped",
+        name,
+        stats.min,
+        stats.max,
+        stats.mean,
+        stats.clipped * 100.0
+    );
+    if config.writes_stdout() {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// convert, post-process each face with `finish`, then rotate and save
+fn convert_and_save<I, P>(
+    config: &Config,
+    img: &I,
+    finish: impl Fn(FaceBuffer<I::Pixel>) -> FaceBuffer<P> + Sync,
+) -> Result<()>
+where
+    I: GenericImageView + Sync,
+    I::Pixel: SamplePixel,
+    P: SamplePixel + PixelWithColorType,
+    [P::Subpixel]: EncodableLayout,
+{
+    // jpg faces have no alpha to premultiply
+    let finish = |face, format| {
+        let mut face = finish(face);
+        if config.premultiply && format != OutputFormat::Jpg {
+            premultiply_alpha(&mut face);
+        }
+        face
+    };
+    match config.projection {
+        Projection::LittlePlanet => {
+            let planet = timed_convert(config, |progress| {
+                finish(little_planet(config, img, progress), config.format.default)
+            });
+            return save_single(config, planet, "planet");
+        }
+        Projection::View => {
+            let view = timed_convert(config, |progress| {
+                finish(
+                    perspective_view(config, img, progress),
+                    config.format.default,
+                )
+            });
+            return save_single(config, view, "view");
+        }
+        Projection::Standard | Projection::Eac => {}
+    }
+    if config.low_memory {
+        let mut faces = Vec::with_capacity(6);
+        let mut thumbnails = Vec::new();
+        timed_convert(config, |progress| {
+            convert_each_with_progress(config, img, progress, |face, side| {
+                let data = orient_faces(config, vec![(finish(face, config.format.of(side)), side)]);
+                print_stats(config, &data[0].0, &side.to_string());
+                save_face(config, &data[0].0, side)?;
+                faces.push((side, data[0].0.width()));
+                if config.contact_sheet {
+                    thumbnails.push((thumbnail(&data[0].0, THUMBNAIL_SIZE), side));
+                }
+                Ok(())
+            })
+        })?;
+        write_manifest(config, faces)?;
+        return save_contact_sheet(config, &thumbnails);
+    }
+    let data = timed_convert(config, |progress| {
+        convert_view_with_progress(config, img, progress)
+            .into_par_iter()
+            .map(|(face, side)| (finish(face, config.format.of(side)), side))
+            .collect()
+    });
+    rotate_and_save(config, data)
+}
+
+/// convert and save the single channel faces of `--scalar`
+fn convert_and_save_scalar(config: &Config, img: &ScalarImage) -> Result<()> {
+    let data = timed_convert(config, |progress| {
+        convert_view_with_progress(config, img, progress)
     });
+    let data = orient_faces(config, data);
+    for (img, side) in &data {
+        print_stats(config, img, &side.to_string());
+    }
+    check_interrupted()?;
+    let start_time = std::time::Instant::now();
+    data.par_iter().try_for_each(|(img, side)| {
+        check_interrupted()?;
+        let format = config.format.of(*side);
+        let path = config
+            .output
+            .join(format!("{}.{}", filename(*side, config.naming), format));
+        let mut writer = BufWriter::new(create_output_file(config, &path)?);
+        write_scalar_image(img, &mut writer, format)
+            .map_err(|e| anyhow::anyhow!(r#"Failed to save "{}": {}"#, path.display(), e))?;
+        writer.flush()?;
+        Ok(())
+    })?;
+    write_manifest(config, data.iter().map(|(img, side)| (*side, img.width())))?;
     let elapsed = start_time.elapsed();
-    println!("Save: {:?}", elapsed);
-    println!(
-        r#"Generated images has been saved in "{}""#,
-        config.output.display()
-    );
+    report_timing(config, "Save", elapsed);
     Ok(())
 }
 
-#[derive(clap::Parser, Debug, Clone)]
-struct Config {
-    /// the image format of the output images
-    #[arg(short, long, value_enum,default_value_t = OutputFormat::Png)]
-    format: OutputFormat,
-    /// interpolation used when sampling source image
-    #[arg(short, long,value_enum, default_value_t = Interpolation::Linear)]
-    interpolation: Interpolation,
-    /// the input equirectangular image's path
-    input: PathBuf,
-    /// the directory to put the output images in, creates if doesn't exist
-    output: PathBuf,
-    #[arg(short, long, default_value_t = 512)]
-    /// size (px) of the output images, width = height
-    size: u32,
-    /// rotate to a z-up skybox if you use it in a y-up renderer
-    #[arg(short, long, default_value_t = false)]
-    rotate: bool,
-}
-#[derive(clap::ValueEnum, Clone, Debug, Copy)]
-enum OutputFormat {
-    Jpg,
-    Png,
-    Webp,
-}
-impl From<OutputFormat> for image::ImageFormat {
-    fn from(value: OutputFormat) -> Self {
-        match value {
-            OutputFormat::Jpg => image::ImageFormat::Jpeg,
-            OutputFormat::Png => image::ImageFormat::Png,
-            OutputFormat::Webp => image::ImageFormat::WebP,
-        }
-    }
-}
-impl Display for OutputFormat {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            OutputFormat::Jpg => write!(f, "jpg"),
-            OutputFormat::Png => write!(f, "png"),
-            OutputFormat::Webp => write!(f, "webp"),
-        }
-    }
-}
-
-#[derive(Clone, Copy)]
-enum Side {
-    Front,
-    Back,
-    Left,
-    Right,
-    Top,
-    Bottom,
-}
-impl Display for Side {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Side::Front => write!(f, "front"),
-            Side::Back => write!(f, "back"),
-            Side::Left => write!(f, "left"),
-            Side::Right => write!(f, "right"),
-            Side::Top => write!(f, "top"),
-            Side::Bottom => write!(f, "bottom"),
-        }
-    }
-}
-
-/// convert 1 equirect image to cubemaps (6 squared images)
-fn convert(config: &Config, img: DynamicImage) -> Vec<(ImageBufferData, Side)> {
-    // use rayon::ParIter;
+fn timed_convert<T>(config: &Config, convert: impl FnOnce(&AtomicU64) -> T) -> T {
+    let start_time = std::time::Instant::now();
     use Side::*;
-    let size = config.size;
-    let interpolation = &config.interpolation;
-    [Front, Back, Left, Right, Top, Bottom]
-        .par_iter()
-        .map(|side| {
-            let size_int = size;
-            let size = size as f32;
-            let mut square = RgbaImage::new(size_int, size_int);
-            for x in 0..size_int {
-                let xf = x as f32;
-                for y in 0..size_int {
-                    let yf = y as f32;
-                    // TODO performance gain if i move the match out of the loop?
-                    let pos = match side {
-                        Front => Vector3::new(0.5, xf / size - 0.5, yf / size - 0.5),
-                        Back => Vector3::new(-0.5, 0.5 - xf / size, yf / size - 0.5),
-                        Left => Vector3::new(-(xf / size - 0.5), 0.5, yf / size - 0.5),
-                        Right => Vector3::new(xf / size - 0.5, -0.5, yf / size - 0.5),
-                        Top => Vector3::new(xf / size - 0.5, 0.5 - yf / size, -0.5),
-                        Bottom => Vector3::new(xf / size - 0.5, yf / size - 0.5, 0.5),
-                    };
-                    let spr = SphericalAngle::from_normalized_vector(pos.normalize());
-                    let uv = spr.to_uv();
-                    let p = interpolation.sample(&img, uv);
-                    square.put_pixel(x, y, p);
+    let total: u64 = match config.projection {
+        Projection::View => config.width as u64 * config.height as u64,
+        Projection::LittlePlanet => (face_size(config, Front) as u64).pow(2),
+        Projection::Standard | Projection::Eac => config
+            .faces
+            .iter()
+            .map(|side| (face_size(config, *side) + 2 * config.edge_pad) as u64)
+            .map(|s| s * s)
+            .sum(),
+    };
+    let progress = AtomicU64::new(0);
+    let done = AtomicBool::new(false);
+    // convert equirect to cubemaps
+    let data = std::thread::scope(|scope| {
+        if !config.quiet && std::io::stderr().is_terminal() {
+            scope.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    let percent = progress.load(Ordering::Relaxed) * 100 / total.max(1);
+                    eprint!("\rConvert: {}%", percent);
+                    std::thread::sleep(Duration::from_millis(100));
                 }
+                eprint!("\r{:16}\r", "");
+            });
+        }
+        let data = convert(&progress);
+        done.store(true, Ordering::Relaxed);
+        data
+    });
+    let elapsed = start_time.elapsed();
+    report_timing(config, "Convert", elapsed);
+    data
+}
+
+fn rotate_and_save<P>(config: &Config, data: Vec<(FaceBuffer<P>, Side)>) -> Result<()>
+where
+    P: SamplePixel + PixelWithColorType,
+    [P::Subpixel]: EncodableLayout,
+{
+    let start_time = std::time::Instant::now();
+    let data = orient_faces(config, data);
+    let elapsed = start_time.elapsed();
+    if config.rotate || config.convention.is_some() || config.orient.is_some() {
+        report_timing(config, "Rotate", elapsed);
+    }
+    for (img, side) in &data {
+        print_stats(config, img, &side.to_string());
+    }
+    check_interrupted()?;
+    let start_time = std::time::Instant::now();
+
+    // write images to disk
+    if config.format.is_cubemap_container() {
+        let mut bytes = Cursor::new(Vec::new());
+        match config.format.default {
+            OutputFormat::Ktx2 => write_ktx2_cubemap(&data, config.color_space, &mut bytes)?,
+            _ => write_dds_cubemap(&data, &mut bytes)?,
+        }
+        if config.writes_stdout() {
+            std::io::stdout().lock().write_all(bytes.get_ref())?;
+        } else {
+            let path = config
+                .output
+                .join(format!("cubemap.{}", config.format.default));
+            create_output_file(config, &path)?.write_all(bytes.get_ref())?;
+        }
+    } else if config.layout != Layout::Separate {
+        let size =
+            config.size.uniform().expect("validated before converting") + 2 * config.edge_pad;
+        let img = match config.layout {
+            Layout::Row => pack_row(&data, &config.face_order, size),
+            _ => pack_horizontal_cross(&data, size),
+        };
+        if config.writes_stdout() {
+            let mut bytes = Cursor::new(Vec::new());
+            write_image(config, &img, &mut bytes, config.format.default)?;
+            std::io::stdout().lock().write_all(bytes.get_ref())?;
+        } else {
+            save_image(
+                config,
+                &img,
+                &config
+                    .output
+                    .join(format!("cubemap.{}", config.format.default)),
+                config.format.default,
+            )?;
+        }
+    } else {
+        data.par_iter()
+            .try_for_each(|(img, side)| save_face(config, img, *side))?;
+    }
+    write_manifest(config, data.iter().map(|(img, side)| (*side, img.width())))?;
+    if config.contact_sheet {
+        let thumbnails: Vec<_> = data
+            .par_iter()
+            .map(|(img, side)| (thumbnail(img, THUMBNAIL_SIZE), *side))
+            .collect();
+        save_contact_sheet(config, &thumbnails)?;
+    }
+    let elapsed = start_time.elapsed();
+    report_timing(config, "Save", elapsed);
+    Ok(())
+}
+
+/// save `contact_sheet.png` with `--contact-sheet`
+fn save_contact_sheet(config: &Config, thumbnails: &[(RgbaImage, Side)]) -> Result<()> {
+    if !config.contact_sheet {
+        return Ok(());
+    }
+    let path = config.output.join("contact_sheet.png");
+    let mut writer = BufWriter::new(create_output_file(config, &path)?);
+    contact_sheet(thumbnails, THUMBNAIL_SIZE)
+        .write_to(&mut writer, ImageFormat::Png)
+        .map_err(|e| anyhow::anyhow!(r#"Failed to save "{}": {}"#, path.display(), e))?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// save a face of the separate layout as `{side}.{format}`, or its tiles with `--tiles`
+fn save_face<P>(config: &Config, img: &FaceBuffer<P>, side: Side) -> Result<()>
+where
+    P: SamplePixel + PixelWithColorType,
+    [P::Subpixel]: EncodableLayout,
+{
+    check_interrupted()?;
+    let name = filename(side, config.naming);
+    let format = config.format.of(side);
+    let save = |img: &FaceBuffer<P>, path: PathBuf| {
+        save_image(config, img, &path, format)
+            .map_err(|e| anyhow::anyhow!(r#"Failed to save "{}": {}"#, path.display(), e))
+    };
+    if config.mips {
+        return generate_mip_chain(img)
+            .par_iter()
+            .enumerate()
+            .try_for_each(|(level, mip)| {
+                save(
+                    mip,
+                    config
+                        .output
+                        .join(format!("{}_mip{}.{}", name, level, format)),
+                )
+            });
+    }
+    if config.tiles > 1 {
+        return tile_image(img, config.tiles)
+            .par_iter()
+            .try_for_each(|((x, y), tile)| {
+                save(
+                    tile,
+                    config
+                        .output
+                        .join(format!("{}_{}_{}.{}", name, x, y, format)),
+                )
+            });
+    }
+    save(img, config.output.join(format!("{}.{}", name, format)))
+}
+
+/// write `index.json` describing the saved faces, given as `(side, size)`, with `--manifest`
+/// or `--tiles`
+fn write_manifest(config: &Config, faces: impl IntoIterator<Item = (Side, u32)>) -> Result<()> {
+    if !config.manifest && config.tiles == 1 {
+        return Ok(());
+    }
+    let faces = faces.into_iter().map(|(side, size)| {
+        let file = if config.format.is_cubemap_container() || config.layout != Layout::Separate {
+            format!("cubemap.{}", config.format.default)
+        } else if config.tiles > 1 {
+            format!(
+                "{}_{{x}}_{{y}}.{}",
+                filename(side, config.naming),
+                config.format.of(side)
+            )
+        } else {
+            format!(
+                "{}.{}",
+                filename(side, config.naming),
+                config.format.of(side)
+            )
+        };
+        (file, side, size)
+    });
+    let mut manifest = Manifest::new(config, faces);
+    if config.mips {
+        // one entry per level, in the order of `mip_sizes`
+        let faces = manifest.faces.drain(..).collect::<Vec<_>>();
+        for face in faces {
+            let name = filename(face.side, config.naming);
+            for (level, size) in mip_sizes(face.size).into_iter().enumerate() {
+                manifest.faces.push(ManifestFace {
+                    file: format!("{}_mip{}.{}", name, level, face.format),
+                    size,
+                    mip: Some(level as u32),
+                    ..face.clone()
+                });
             }
-            (square, *side)
-        })
-        .collect()
-}
-fn rotate(entries: Vec<(ImageBufferData, Side)>) -> Vec<(ImageBufferData, Side)> {
-    use image::imageops::*;
-    entries
-        .into_par_iter()
-        .map(|(img, side)| {
-            let image = match side {
-                Side::Top => img,
-                Side::Bottom => rotate180(&img),
-                Side::Left => rotate180(&img),
-                Side::Right => img,
-                Side::Front => rotate270(&img),
-                Side::Back => rotate90(&img),
-            };
-            (image, side)
-        })
-        .collect()
+        }
+    }
+    create_output_file(config, &config.output.join("index.json"))?
+        .write_all(manifest.to_json().as_bytes())?;
+    Ok(())
+}
+
+/// save the image of a projection that doesn't produce faces as `{name}.{format}`
+fn save_single<P>(config: &Config, mut img: FaceBuffer<P>, name: &str) -> Result<()>
+where
+    P: SamplePixel + PixelWithColorType,
+    [P::Subpixel]: EncodableLayout,
+{
+    let start_time = std::time::Instant::now();
+    if config.flip_x {
+        image::imageops::flip_horizontal_in_place(&mut img);
+    }
+    if config.flip_y {
+        image::imageops::flip_vertical_in_place(&mut img);
+    }
+    print_stats(config, &img, name);
+    check_interrupted()?;
+    if config.writes_stdout() {
+        let mut bytes = Cursor::new(Vec::new());
+        write_image(config, &img, &mut bytes, config.format.default)?;
+        std::io::stdout().lock().write_all(bytes.get_ref())?;
+    } else {
+        let path = config
+            .output
+            .join(format!("{}.{}", name, config.format.default));
+        save_image(config, &img, &path, config.format.default)?;
+    }
+    let elapsed = start_time.elapsed();
+    report_timing(config, "Save", elapsed);
+    Ok(())
+}
+
+/// read six faces named by side from the input directory and save them as 1 equirect image
+fn reverse(config: &Config) -> Result<()> {
+    if config.format.is_hdr() {
+        reverse_as(config, DynamicImage::into_rgba32f)
+    } else {
+        reverse_as(config, DynamicImage::into_rgba8)
+    }
+}
+
+fn reverse_as<P>(config: &Config, into_face: fn(DynamicImage) -> FaceBuffer<P>) -> Result<()>
+where
+    P: SamplePixel + PixelWithColorType,
+    [P::Subpixel]: EncodableLayout,
+{
+    let start_time = std::time::Instant::now();
+    let faces = load_cubemap_dir(&config.input, config.naming, into_face)?.into_entries();
+    report_timing(config, "Read and Parse", start_time.elapsed());
+    let size = faces[0].0.width();
+
+    create_dir_all(&config.output)?;
+    let start_time = std::time::Instant::now();
+    let img = cubemap_to_equirect(&faces, size * 4, size * 2, &config.interpolation);
+    report_timing(config, "Convert", start_time.elapsed());
+    check_interrupted()?;
+    let start_time = std::time::Instant::now();
+    let output = config
+        .output
+        .join(format!("equirect.{}", config.format.default));
+    save_image(config, &img, &output, config.format.default)?;
+    report_timing(config, "Save", start_time.elapsed());
+    if config.json {
+        WRITTEN
+            .lock()
+            .expect("a conversion panicked")
+            .push(output.clone());
+    }
+    print_timing_json(config);
+    if !config.quiet {
+        println!(
+            r#"Generated image has been saved in "{}""#,
+            output.display()
+        );
+    }
+    Ok(())
 }
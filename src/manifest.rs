@@ -0,0 +1,118 @@
+use clap::ValueEnum;
+
+use crate::{Config, OutputFormat, Side};
+
+/// machine readable description of the saved output, written as `index.json`
+#[derive(Clone, Debug)]
+pub struct Manifest {
+    pub faces: Vec<ManifestFace>,
+    /// each face is split into tiles x tiles images, 1 if they aren't split
+    pub tiles: u32,
+    pub interpolation: String,
+    pub rotate: bool,
+    pub tone_map_operator: String,
+    pub exposure: f32,
+}
+
+/// one side of the cubemap and the file it was saved in
+#[derive(Clone, Debug)]
+pub struct ManifestFace {
+    /// file name relative to the output directory, shared by all sides in packed layouts,
+    /// with `{x}` and `{y}` standing for the column and row of the tile when they are split
+    pub file: String,
+    pub side: Side,
+    /// size (px) of the side, width = height
+    pub size: u32,
+    pub format: OutputFormat,
+    /// level of the mip chain with `--mips`
+    pub mip: Option<u32>,
+}
+
+impl Manifest {
+    /// describe `faces` as `(file, side, size)` saved with `config`
+    pub fn new(config: &Config, faces: impl IntoIterator<Item = (String, Side, u32)>) -> Self {
+        Self {
+            faces: faces
+                .into_iter()
+                .map(|(file, side, size)| ManifestFace {
+                    file,
+                    side,
+                    size,
+                    format: config.format.of(side),
+                    mip: None,
+                })
+                .collect(),
+            tiles: config.tiles,
+            interpolation: value_name(&config.interpolation),
+            rotate: config.rotate,
+            tone_map_operator: value_name(&config.tone_map_operator),
+            exposure: config.exposure,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let faces: Vec<String> = self
+            .faces
+            .iter()
+            .map(|face| {
+                let mip = face
+                    .mip
+                    .map(|level| format!(r#", "mip": {}"#, level))
+                    .unwrap_or_default();
+                format!(
+                    r#"    {{ "file": {}, "side": "{}", "size": {}, "format": "{}"{} }}"#,
+                    json_string(&face.file),
+                    face.side,
+                    face.size,
+                    face.format,
+                    mip
+                )
+            })
+            .collect();
+        format!(
+            "{{\n  \"faces\": [\n{}\n  ],\n  \"tiles\": {},\n  \"config\": {{\n    \"interpolation\": \"{}\",\n    \"rotate\": {},\n    \"tone_map_operator\": \"{}\",\n    \"exposure\": {}\n  }}\n}}\n",
+            faces.join(",\n"),
+            self.tiles,
+            self.interpolation,
+            self.rotate,
+            self.tone_map_operator,
+            json_number(self.exposure)
+        )
+    }
+}
+
+/// the name the value is given on the command line
+fn value_name(value: &impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_owned())
+        .unwrap_or_default()
+}
+
+/// `s` as a quoted json string
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// json has no nan or infinity
+fn json_number(n: f32) -> String {
+    if n.is_finite() {
+        n.to_string()
+    } else {
+        "null".to_owned()
+    }
+}
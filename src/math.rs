@@ -1,98 +1,1071 @@
-use image::{imageops::sample_nearest, DynamicImage, Rgba};
-
-/// spherical coord without radius
-#[derive(Debug)]
-pub struct SphericalAngle {
-    /// rotation on pole axis
-    pub theta: f32,
-    /// angle on equator
-    pub phi: f32,
-}
-
-impl SphericalAngle {
-    pub fn from_normalized_vector(value: Vector3) -> Self {
-        let theta = value.y.atan2(value.x);
-        let phi = value.z.asin();
-        Self { theta, phi }
-    }
-    pub fn to_uv(&self) -> (f32, f32) {
-        use std::f32::consts::PI;
-
-        (self.theta / (2.0 * PI) + 0.5, self.phi / PI + 0.5)
-    }
-}
-
-#[derive(clap::ValueEnum, Clone, Debug)]
-pub enum Interpolation {
-    Linear,
-    Nearest,
-}
-impl Interpolation {
-    pub fn sample(&self, img: &DynamicImage, uv: (f32, f32)) -> Rgba<u8> {
-        use image::imageops::sample_bilinear;
-        match self {
-            Self::Linear => sample_bilinear(img, uv.0, uv.1),
-            Self::Nearest => sample_nearest(img, uv.0, uv.1),
-        }
-        .unwrap_or(Rgba([0, 0, 0, 255]))
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
-pub struct Vector3 {
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
-}
-
-impl Vector3 {
-    pub fn new(x: f32, y: f32, z: f32) -> Self {
-        Self { x, y, z }
-    }
-    /// return a normalized version of this vector
-    pub fn normalize(&self) -> Self {
-        let len = self.len();
-        Self {
-            x: self.x / len,
-            y: self.y / len,
-            z: self.z / len,
-        }
-    }
-    /// make this vector3 normalized
-    // pub fn normalize_mut(&mut self) {
-    //     let len = self.len();
-    //     self.x /= len;
-    //     self.y /= len;
-    //     self.z /= len;
-    // }
-    pub fn len(&self) -> f32 {
-        (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
-    }
-    // pub fn len_squared(&self) -> f32 {
-    //     self.x * self.x + self.y * self.y + self.z * self.z
-    // }
-}
-
-// impl std::ops::Add for Vector3 {
-//     type Output = Vector3;
-
-//     fn add(self, rhs: Self) -> Self::Output {
-//         Vector3 {
-//             x: self.x + rhs.x,
-//             y: self.y + rhs.y,
-//             z: self.z + rhs.z,
-//         }
-//     }
-// }
-
-// impl std::ops::Sub for Vector3 {
-//     type Output = Vector3;
-
-//     fn sub(self, rhs: Self) -> Self::Output {
-//         Vector3 {
-//             x: self.x - rhs.x,
-//             y: self.y - rhs.y,
-//             z: self.z - rhs.z,
-//         }
-//     }
-// }
+use image::{
+    imageops::sample_nearest, DynamicImage, GenericImageView, ImageBuffer, Luma, Pixel, Rgb, Rgba,
+};
+
+/// spherical coord without radius
+#[derive(Debug)]
+pub struct SphericalAngle {
+    /// rotation on pole axis
+    pub theta: f32,
+    /// angle on equator
+    pub phi: f32,
+}
+
+impl SphericalAngle {
+    pub fn from_normalized_vector(value: Vector3) -> Self {
+        // theta is undefined at the poles, pick 0 so it doesn't depend on rounding noise
+        let theta = if value.x.abs() < f32::EPSILON && value.y.abs() < f32::EPSILON {
+            0.0
+        } else {
+            value.y.atan2(value.x)
+        };
+        // rounding can push z slightly out of [-1, 1] where asin is NaN
+        let phi = value.z.clamp(-1.0, 1.0).asin();
+        Self { theta, phi }
+    }
+    /// same as `from_normalized_vector`, with `pole` as the axis mapped to phi
+    pub fn from_normalized_vector_with_pole(value: Vector3, pole: PoleAxis) -> Self {
+        Self::from_normalized_vector(pole.to_z(value))
+    }
+    /// inverse of `to_uv`
+    pub fn from_uv(u: f32, v: f32) -> Self {
+        use std::f32::consts::PI;
+
+        let theta = (u - 0.5) * 2.0 * PI;
+        let phi = (v - 0.5) * PI;
+        Self { theta, phi }
+    }
+    /// unit vector pointing at the angle, inverse of `from_normalized_vector`
+    pub fn to_direction(&self) -> Vector3 {
+        let (sin_phi, cos_phi) = self.phi.sin_cos();
+        let (sin_theta, cos_theta) = self.theta.sin_cos();
+        Vector3::new(cos_phi * cos_theta, cos_phi * sin_theta, sin_phi)
+    }
+    pub fn to_uv(&self) -> (f32, f32) {
+        use std::f32::consts::PI;
+
+        (self.theta / (2.0 * PI) + 0.5, self.phi / PI + 0.5)
+    }
+    /// same as `to_uv`, with `offset` (radians) added to theta, u wraps around into [0, 1)
+    pub fn to_uv_with_offset(&self, offset: f32) -> (f32, f32) {
+        use std::f32::consts::PI;
+
+        let u = ((self.theta + offset) / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        (u, self.phi / PI + 0.5)
+    }
+    /// same as `to_uv_with_offset` for an image in `projection` whose height / width is
+    /// `aspect`, v is outside of [0, 1] past the vertical extent of the image, fisheye images
+    /// are taken with a 180 degree lens, see `to_fisheye_uv` for others
+    pub fn to_projected_uv(
+        &self,
+        projection: InputProjection,
+        aspect: f32,
+        offset: f32,
+    ) -> (f32, f32) {
+        match projection {
+            InputProjection::Mirrorball => mirror_ball_uv(self.turned(offset), aspect),
+            InputProjection::Fisheye => self.to_fisheye_uv(aspect, offset, std::f32::consts::PI),
+            _ => {
+                let (u, _) = self.to_uv_with_offset(offset);
+                (u, projection.v(self.phi, aspect))
+            }
+        }
+    }
+    /// uv in a fisheye image whose height / width is `aspect`, taken with a lens covering
+    /// `lens_fov` (radians) looking at the front, with `offset` (radians) added to theta,
+    /// directions outside of the lens get a v of -1
+    pub fn to_fisheye_uv(&self, aspect: f32, offset: f32, lens_fov: f32) -> (f32, f32) {
+        fisheye_uv(self.turned(offset), aspect, lens_fov)
+    }
+    /// inverse of `to_projected_uv` without an offset
+    pub fn from_projected_uv(u: f32, v: f32, projection: InputProjection, aspect: f32) -> Self {
+        match projection {
+            InputProjection::Mirrorball => {
+                return Self::from_normalized_vector(mirror_ball_direction(u, v, aspect));
+            }
+            InputProjection::Fisheye => {
+                return Self::from_fisheye_uv(u, v, aspect, std::f32::consts::PI);
+            }
+            _ => {}
+        }
+        let Self { theta, .. } = Self::from_uv(u, v);
+        Self {
+            theta,
+            phi: projection.phi(v, aspect),
+        }
+    }
+    /// inverse of `to_fisheye_uv` without an offset
+    pub fn from_fisheye_uv(u: f32, v: f32, aspect: f32, lens_fov: f32) -> Self {
+        Self::from_normalized_vector(fisheye_direction(u, v, aspect, lens_fov))
+    }
+    /// unit vector of the angle with `offset` (radians) added to theta
+    fn turned(&self, offset: f32) -> Vector3 {
+        Self {
+            theta: self.theta + offset,
+            phi: self.phi,
+        }
+        .to_direction()
+    }
+}
+
+/// how the input image maps directions to its pixels, all but the mirror ball and the fisheye
+/// map latitude to the rows and span 360 degrees of longitude linearly over the width
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputProjection {
+    /// latitude grows linearly from the zenith at the top to the nadir at the bottom
+    Equirect,
+    /// central cylindrical, rows are spaced by the tangent of the latitude with the same scale
+    /// as the columns, the image covers as far up and down as its aspect ratio reaches
+    Cylindrical,
+    /// mercator, rows are spaced by the inverse gudermannian of the latitude with the same
+    /// scale as the columns, web mercator tiles are square and reach about 85 degrees
+    Mercator,
+    /// a photo of a chrome ball (light probe) taken from the back looking at the front, the
+    /// ball is the circle inscribed in the centered square of the image, its center reflects the
+    /// back and its rim the front
+    Mirrorball,
+    /// an equidistant circular fisheye looking at the front, the angle from the front grows
+    /// linearly from the center of the circle inscribed in the centered square of the image to
+    /// half the lens fov at its rim
+    Fisheye,
+}
+impl InputProjection {
+    /// v (0 at the top) of the latitude `phi` (radians, negative up), `aspect` is the height /
+    /// width of the image, equirect images ignore it, panics for the mirror ball and the
+    /// fisheye whose rows aren't latitudes
+    pub fn v(&self, phi: f32, aspect: f32) -> f32 {
+        use std::f32::consts::PI;
+
+        // y on the projection plane of an image whose width spans 2 pi
+        let y = match self {
+            Self::Equirect => return phi / PI + 0.5,
+            Self::Cylindrical => phi.tan(),
+            Self::Mercator => phi.tan().asinh(),
+            Self::Mirrorball | Self::Fisheye => unreachable!("the rows aren't latitudes"),
+        };
+        y / (2.0 * PI * aspect) + 0.5
+    }
+    /// latitude of the row at `v`, inverse of `v`
+    pub fn phi(&self, v: f32, aspect: f32) -> f32 {
+        use std::f32::consts::PI;
+
+        let y = (v - 0.5) * 2.0 * PI * aspect;
+        match self {
+            Self::Equirect => (v - 0.5) * PI,
+            Self::Cylindrical => y.atan(),
+            // the gudermannian function
+            Self::Mercator => y.sinh().atan(),
+            Self::Mirrorball | Self::Fisheye => unreachable!("the rows aren't latitudes"),
+        }
+    }
+}
+
+/// scale of the radius of a circle filling the shorter side of an image whose height / width is
+/// `aspect` to its u and v
+fn inscribed_circle_scale(aspect: f32) -> (f32, f32) {
+    if aspect <= 1.0 {
+        (aspect, 1.0)
+    } else {
+        (1.0, 1.0 / aspect)
+    }
+}
+
+/// uv of the point on a mirror ball reflecting the unit vector `direction` to a camera looking
+/// along +x, the ball's normal there halves the angle between the direction and the camera,
+/// the front (+x) itself is the whole rim and gets a v of -1 so it isn't sampled
+fn mirror_ball_uv(direction: Vector3, aspect: f32) -> (f32, f32) {
+    let normal = Vector3::new(direction.x - 1.0, direction.y, direction.z);
+    let length = normal.len();
+    if length < 1e-6 {
+        return (0.5, -1.0);
+    }
+    let (su, sv) = inscribed_circle_scale(aspect);
+    // image right is +y and image down is +z for a camera looking along +x
+    (
+        0.5 + normal.y / length * 0.5 * su,
+        0.5 + normal.z / length * 0.5 * sv,
+    )
+}
+
+/// inverse of `mirror_ball_uv`, uv outside of the ball are pulled onto its rim
+fn mirror_ball_direction(u: f32, v: f32, aspect: f32) -> Vector3 {
+    let (su, sv) = inscribed_circle_scale(aspect);
+    let y = (u - 0.5) * 2.0 / su;
+    let z = (v - 0.5) * 2.0 / sv;
+    let r = (y * y + z * z).sqrt();
+    let (y, z) = if r > 1.0 { (y / r, z / r) } else { (y, z) };
+    // the normal faces the camera, the view ray +x reflects off it
+    let x = -(1.0 - y * y - z * z).max(0.0).sqrt();
+    Vector3::new(1.0 - 2.0 * x * x, -2.0 * x * y, -2.0 * x * z)
+}
+
+/// uv of the unit vector `direction` in an equidistant fisheye image looking along +x with a
+/// lens covering `lens_fov` (radians), directions outside of the lens get a v of -1 so they
+/// aren't sampled
+fn fisheye_uv(direction: Vector3, aspect: f32, lens_fov: f32) -> (f32, f32) {
+    let sideways = (direction.y * direction.y + direction.z * direction.z).sqrt();
+    let angle = sideways.atan2(direction.x);
+    if angle > lens_fov * 0.5 {
+        return (0.5, -1.0);
+    }
+    // radius on the image, 1 at the rim, along the direction's projection on the image plane
+    let r = angle / (lens_fov * 0.5);
+    let (y, z) = if sideways < 1e-6 {
+        (0.0, 0.0)
+    } else {
+        (direction.y / sideways * r, direction.z / sideways * r)
+    };
+    let (su, sv) = inscribed_circle_scale(aspect);
+    // image right is +y and image down is +z
+    (0.5 + y * 0.5 * su, 0.5 + z * 0.5 * sv)
+}
+
+/// inverse of `fisheye_uv`, uv outside of the circle are pulled onto its rim
+fn fisheye_direction(u: f32, v: f32, aspect: f32, lens_fov: f32) -> Vector3 {
+    let (su, sv) = inscribed_circle_scale(aspect);
+    let y = (u - 0.5) * 2.0 / su;
+    let z = (v - 0.5) * 2.0 / sv;
+    let r = (y * y + z * z).sqrt();
+    if r < 1e-6 {
+        return Vector3::new(1.0, 0.0, 0.0);
+    }
+    let (sin, cos) = (r.min(1.0) * lens_fov * 0.5).sin_cos();
+    Vector3::new(cos, y / r * sin, z / r * sin)
+}
+
+/// the axis through the poles of the panorama
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PoleAxis {
+    X,
+    Y,
+    Z,
+}
+impl PoleAxis {
+    /// cyclically permute the axes so the pole axis becomes z, keeping the handedness
+    pub fn to_z(&self, v: Vector3) -> Vector3 {
+        match self {
+            Self::X => Vector3::new(v.y, v.z, v.x),
+            Self::Y => Vector3::new(v.z, v.x, v.y),
+            Self::Z => v,
+        }
+    }
+}
+
+/// how a texel index past an edge of the image is brought back onto it
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressMode {
+    /// around to the opposite edge
+    Wrap,
+    /// onto the edge texel
+    Clamp,
+    /// reflected back at the edge
+    Mirror,
+}
+impl AddressMode {
+    /// the texel of index `i` on an axis `size` texels long
+    pub fn resolve(&self, i: i64, size: u32) -> u32 {
+        let size = size as i64;
+        (match self {
+            Self::Wrap => i.rem_euclid(size),
+            Self::Clamp => i.clamp(0, size - 1),
+            Self::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size {
+                    i
+                } else {
+                    2 * size - 1 - i
+                }
+            }
+        }) as u32
+    }
+}
+
+/// address modes of the columns (u) and rows (v) of an image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Addressing {
+    pub u: AddressMode,
+    pub v: AddressMode,
+}
+impl Addressing {
+    /// u wraps around since the panorama is cyclic in longitude, v is clamped at the poles
+    pub const PANORAMA: Self = Self {
+        u: AddressMode::Wrap,
+        v: AddressMode::Clamp,
+    };
+    /// both clamped, for images that aren't cyclic
+    pub const CLAMPED: Self = Self {
+        u: AddressMode::Clamp,
+        v: AddressMode::Clamp,
+    };
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum Interpolation {
+    Linear,
+    Nearest,
+    /// catmull-rom over a 4x4 neighborhood, sharper than linear
+    Bicubic,
+    /// average of the texels covered by the output pixel, reduces aliasing when the source is
+    /// much larger than the faces, same as linear where the footprint is unknown
+    Area,
+    /// linear sampling of the two mip levels closest to the size of the output pixel, blended,
+    /// cheaper than area for large downsampling but blurrier near the poles, same as linear
+    /// where the footprint is unknown
+    Trilinear,
+    /// windowed sinc over a 6x6 neighborhood, the sharpest and the slowest (more than twice
+    /// the time of bicubic), rings slightly at hard edges
+    Lanczos3,
+}
+impl Interpolation {
+    pub fn sample<P: SamplePixel>(
+        &self,
+        img: &impl GenericImageView<Pixel = P>,
+        uv: (f32, f32),
+    ) -> P {
+        self.try_sample(img, uv).unwrap_or(P::FALLBACK)
+    }
+    /// same as `sample`, but `None` where sampling fails instead of `FALLBACK`
+    pub fn try_sample<P: SamplePixel>(
+        &self,
+        img: &impl GenericImageView<Pixel = P>,
+        uv: (f32, f32),
+    ) -> Option<P> {
+        self.try_sample_addressed(img, uv, Addressing::PANORAMA)
+    }
+    /// same as `try_sample`, the kernel's texels past the edges are found with `addressing`
+    pub fn try_sample_addressed<P: SamplePixel>(
+        &self,
+        img: &impl GenericImageView<Pixel = P>,
+        uv: (f32, f32),
+        addressing: Addressing,
+    ) -> Option<P> {
+        match self {
+            Self::Linear => sample_separable(img, uv.0, uv.1, addressing, 1, tent),
+            Self::Nearest => sample_nearest(img, uv.0, uv.1),
+            Self::Bicubic => sample_separable(img, uv.0, uv.1, addressing, 2, catmull_rom),
+            Self::Area | Self::Trilinear => sample_separable(img, uv.0, uv.1, addressing, 1, tent),
+            Self::Lanczos3 => sample_separable(img, uv.0, uv.1, addressing, 3, lanczos3),
+        }
+    }
+    /// `try_sample_addressed` of each uv of `uvs` into `out`, with the `simd` feature on x86_64
+    /// `Linear` samples four uvs at a time
+    pub fn try_sample_batch<P: SamplePixel>(
+        &self,
+        img: &impl GenericImageView<Pixel = P>,
+        uvs: &[(f32, f32)],
+        addressing: Addressing,
+        out: &mut [Option<P>],
+    ) {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        if let Self::Linear = self {
+            return crate::simd::sample_bilinear(img, uvs, addressing, out);
+        }
+        for (uv, out) in uvs.iter().zip(out) {
+            *out = self.try_sample_addressed(img, *uv, addressing);
+        }
+    }
+    /// same as `sample`, `footprint` is the size in uv of the output pixel, only used by `Area`
+    pub fn sample_footprint<P: SamplePixel>(
+        &self,
+        img: &impl GenericImageView<Pixel = P>,
+        uv: (f32, f32),
+        footprint: (f32, f32),
+    ) -> P {
+        self.try_sample_footprint(img, uv, footprint)
+            .unwrap_or(P::FALLBACK)
+    }
+    /// same as `sample_footprint`, but `None` where sampling fails instead of `FALLBACK`
+    pub fn try_sample_footprint<P: SamplePixel>(
+        &self,
+        img: &impl GenericImageView<Pixel = P>,
+        uv: (f32, f32),
+        footprint: (f32, f32),
+    ) -> Option<P> {
+        self.try_sample_mips(img, &[], uv, footprint, Addressing::PANORAMA)
+    }
+    /// same as `try_sample_footprint`, `mips` are the levels after `img` from `build_mip_levels`,
+    /// only used by `Trilinear`, texels past the edges are found with `addressing`
+    pub fn try_sample_mips<P: SamplePixel>(
+        &self,
+        img: &impl GenericImageView<Pixel = P>,
+        mips: &[ImageBuffer<P, Vec<P::Subpixel>>],
+        uv: (f32, f32),
+        footprint: (f32, f32),
+        addressing: Addressing,
+    ) -> Option<P> {
+        match self {
+            Self::Area => sample_area(img, uv, footprint, addressing),
+            Self::Trilinear => sample_trilinear(img, mips, uv, footprint, addressing),
+            _ => self.try_sample_addressed(img, uv, addressing),
+        }
+    }
+    /// same as `sample`, but u is clamped instead of wrapped, for images that aren't cyclic
+    pub fn sample_clamped<P: SamplePixel>(
+        &self,
+        img: &impl GenericImageView<Pixel = P>,
+        uv: (f32, f32),
+    ) -> P {
+        self.try_sample_addressed(img, uv, Addressing::CLAMPED)
+            .unwrap_or(P::FALLBACK)
+    }
+}
+
+/// sampling strategy of `convert_with_sampler`, implement it to plug in other filters
+pub trait Sampler {
+    /// color of the equirect image at uv in [0, 1], u wraps around since the panorama is cyclic
+    /// in longitude
+    fn sample(&self, img: &DynamicImage, uv: (f32, f32)) -> Rgba<u8>;
+}
+impl Sampler for Interpolation {
+    fn sample(&self, img: &DynamicImage, uv: (f32, f32)) -> Rgba<u8> {
+        Interpolation::sample(self, img, uv)
+    }
+}
+
+/// sample with a separable kernel of the given radius (in px) using coordinates in [0, 1], the
+/// kernel's texels past the edges are found with `addressing`, v outside of [0, 1] fails
+fn sample_separable<P: SamplePixel>(
+    img: &impl GenericImageView<Pixel = P>,
+    u: f32,
+    v: f32,
+    addressing: Addressing,
+    radius: i64,
+    kernel: impl Fn(f32) -> f32,
+) -> Option<P> {
+    let (w, h) = img.dimensions();
+    if w == 0 || h == 0 || !(0.0..=1.0).contains(&v) {
+        return None;
+    }
+    let x = w as f32 * u - 0.5;
+    let y = h as f32 * v - 0.5;
+    let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+    // the column weights are the same for every row, radius is at most 3
+    let columns = (x0 - radius + 1)..=(x0 + radius);
+    let mut wx = [0.0; 6];
+    for (k, i) in columns.clone().enumerate() {
+        wx[k] = kernel(x - i as f32);
+    }
+    let mut sum = [0.0; 4];
+    let mut weight_sum = 0.0;
+    for j in (y0 - radius + 1)..=(y0 + radius) {
+        let wy = kernel(y - j as f32);
+        let py = addressing.v.resolve(j, h);
+        for (k, i) in columns.clone().enumerate() {
+            let weight = wy * wx[k];
+            let px = addressing.u.resolve(i, w);
+            let c = img.get_pixel(px, py).to_f32();
+            for (s, c) in sum.iter_mut().zip(c) {
+                *s += c * weight;
+            }
+            weight_sum += weight;
+        }
+    }
+    Some(P::from_f32(sum.map(|s| s / weight_sum)))
+}
+
+/// box filter over a footprint (in uv) centered at uv, texels are weighted by how much of them
+/// is covered, columns past the edges are found with `addressing` and rows are cut off at them
+fn sample_area<P: SamplePixel>(
+    img: &impl GenericImageView<Pixel = P>,
+    uv: (f32, f32),
+    footprint: (f32, f32),
+    addressing: Addressing,
+) -> Option<P> {
+    let (w, h) = img.dimensions();
+    if w == 0 || h == 0 || !(0.0..=1.0).contains(&uv.1) {
+        return None;
+    }
+    // the footprint covers at least one texel so it never falls between texel centers
+    let half_w = (footprint.0 * w as f32).clamp(1.0, w as f32) / 2.0;
+    let half_h = (footprint.1 * h as f32).clamp(1.0, h as f32) / 2.0;
+    let (x, y) = (uv.0 * w as f32, uv.1 * h as f32);
+    let (x0, x1) = (x - half_w, x + half_w);
+    let (y0, y1) = ((y - half_h).max(0.0), (y + half_h).min(h as f32));
+    let coverage = |i: i64, lo: f32, hi: f32| (hi.min(i as f32 + 1.0) - lo.max(i as f32)).max(0.0);
+    let mut sum = [0.0; 4];
+    let mut weight_sum = 0.0;
+    for j in (y0.floor() as i64)..(y1.ceil() as i64) {
+        let wy = coverage(j, y0, y1);
+        let py = j.clamp(0, h as i64 - 1) as u32;
+        for i in (x0.floor() as i64)..(x1.ceil() as i64) {
+            let weight = wy * coverage(i, x0, x1);
+            let px = addressing.u.resolve(i, w);
+            let c = img.get_pixel(px, py).to_f32();
+            for (s, c) in sum.iter_mut().zip(c) {
+                *s += c * weight;
+            }
+            weight_sum += weight;
+        }
+    }
+    if weight_sum <= 0.0 {
+        return None;
+    }
+    Some(P::from_f32(sum.map(|s| s / weight_sum)))
+}
+
+/// blend linear samples of the two levels whose texels are closest to the footprint (in uv),
+/// level 0 is `img` and level n is `mips[n - 1]`
+fn sample_trilinear<P: SamplePixel>(
+    img: &impl GenericImageView<Pixel = P>,
+    mips: &[ImageBuffer<P, Vec<P::Subpixel>>],
+    uv: (f32, f32),
+    footprint: (f32, f32),
+    addressing: Addressing,
+) -> Option<P> {
+    let (w, h) = img.dimensions();
+    let texels = (footprint.0 * w as f32).max(footprint.1 * h as f32);
+    let lod = texels.max(1.0).log2().min(mips.len() as f32);
+    let level = lod.floor() as usize;
+    let t = lod - level as f32;
+    let sample_level = |level: usize| match level {
+        0 => sample_separable(img, uv.0, uv.1, addressing, 1, tent),
+        n => sample_separable(&mips[n - 1], uv.0, uv.1, addressing, 1, tent),
+    };
+    let near = sample_level(level)?;
+    if t == 0.0 || level == mips.len() {
+        return Some(near);
+    }
+    let far = sample_level(level + 1)?.to_f32();
+    let mut channels = near.to_f32();
+    for (c, f) in channels.iter_mut().zip(far) {
+        *c += (f - *c) * t;
+    }
+    Some(P::from_f32(channels))
+}
+
+/// halve `img` repeatedly down to 1x1 with a box filter, the full resolution level isn't
+/// included, odd sizes round down and each texel averages the area it covers so the last column
+/// (the seam at u = 1) isn't dropped
+pub fn build_mip_levels<P: SamplePixel>(
+    img: &impl GenericImageView<Pixel = P>,
+) -> Vec<ImageBuffer<P, Vec<P::Subpixel>>> {
+    let mut levels: Vec<ImageBuffer<P, Vec<P::Subpixel>>> = Vec::new();
+    let (mut w, mut h) = img.dimensions();
+    while w > 1 || h > 1 {
+        let (next_w, next_h) = ((w / 2).max(1), (h / 2).max(1));
+        let get = |x: u32, y: u32| match levels.last() {
+            Some(prev) => prev.get_pixel(x.min(w - 1), y.min(h - 1)).to_f32(),
+            None => img.get_pixel(x.min(w - 1), y.min(h - 1)).to_f32(),
+        };
+        // source texels under a texel of the next level with how much of them it covers, two
+        // for even sizes and up to four when an odd size is rounded down
+        let spans = |size: u32, next: u32, i: u32| {
+            let scale = size as f32 / next as f32;
+            let (lo, hi) = (i as f32 * scale, (i + 1) as f32 * scale);
+            (lo.floor() as u32..(hi.ceil() as u32).min(size))
+                .map(move |j| (j, (hi.min(j as f32 + 1.0) - lo.max(j as f32)).max(0.0)))
+        };
+        let next = ImageBuffer::from_fn(next_w, next_h, |x, y| {
+            let mut sum = [0.0; 4];
+            let mut weight_sum = 0.0;
+            for (sy, wy) in spans(h, next_h, y) {
+                for (sx, wx) in spans(w, next_w, x) {
+                    let weight = wx * wy;
+                    for (s, c) in sum.iter_mut().zip(get(sx, sy)) {
+                        *s += c * weight;
+                    }
+                    weight_sum += weight;
+                }
+            }
+            P::from_f32(sum.map(|s| s / weight_sum))
+        });
+        levels.push(next);
+        (w, h) = (next_w, next_h);
+    }
+    levels
+}
+
+/// mip pyramid of an equirect image for `Interpolation::Trilinear`, the levels after the full
+/// resolution one, in the pixel type the image is sampled as
+pub fn build_mips(img: &DynamicImage) -> Vec<DynamicImage> {
+    match img {
+        DynamicImage::ImageRgba8(img) => {
+            build_mip_levels(img).into_iter().map(Into::into).collect()
+        }
+        DynamicImage::ImageRgba16(img) => {
+            build_mip_levels(img).into_iter().map(Into::into).collect()
+        }
+        DynamicImage::ImageRgba32F(img) => {
+            build_mip_levels(img).into_iter().map(Into::into).collect()
+        }
+        img => build_mip_levels(&img.to_rgba32f())
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+    }
+}
+
+fn tent(d: f32) -> f32 {
+    (1.0 - d.abs()).max(0.0)
+}
+
+fn catmull_rom(d: f32) -> f32 {
+    let d = d.abs();
+    if d < 1.0 {
+        1.5 * d * d * d - 2.5 * d * d + 1.0
+    } else if d < 2.0 {
+        -0.5 * d * d * d + 2.5 * d * d - 4.0 * d + 2.0
+    } else {
+        0.0
+    }
+}
+
+/// sinc windowed by a sinc three times as wide, zero from 3 texels away
+fn lanczos3(d: f32) -> f32 {
+    use std::f32::consts::PI;
+
+    let d = d.abs();
+    if d < f32::EPSILON {
+        1.0
+    } else if d < 3.0 {
+        let x = PI * d;
+        3.0 * x.sin() * (x / 3.0).sin() / (x * x)
+    } else {
+        0.0
+    }
+}
+
+/// pixel types that can be sampled from the source image
+pub trait SamplePixel: Pixel<Subpixel: Send + Sync> + Send + Sync + 'static {
+    /// returned when sampling fails
+    const FALLBACK: Self;
+    /// channel value of full intensity
+    const CHANNEL_MAX: f32;
+    /// channels as floats, in the range of the subpixel type
+    fn to_f32(&self) -> [f32; 4];
+    /// clamps and rounds the channels into the range of the subpixel type
+    fn from_f32(channels: [f32; 4]) -> Self;
+}
+impl SamplePixel for Rgba<u8> {
+    const FALLBACK: Self = Rgba([0, 0, 0, u8::MAX]);
+    const CHANNEL_MAX: f32 = u8::MAX as f32;
+    fn to_f32(&self) -> [f32; 4] {
+        self.0.map(|c| c as f32)
+    }
+    fn from_f32(channels: [f32; 4]) -> Self {
+        Rgba(channels.map(|c| c.round().clamp(0.0, u8::MAX as f32) as u8))
+    }
+}
+impl SamplePixel for Rgba<u16> {
+    const FALLBACK: Self = Rgba([0, 0, 0, u16::MAX]);
+    const CHANNEL_MAX: f32 = u16::MAX as f32;
+    fn to_f32(&self) -> [f32; 4] {
+        self.0.map(|c| c as f32)
+    }
+    fn from_f32(channels: [f32; 4]) -> Self {
+        Rgba(channels.map(|c| c.round().clamp(0.0, u16::MAX as f32) as u16))
+    }
+}
+impl SamplePixel for Rgba<f32> {
+    const FALLBACK: Self = Rgba([0.0, 0.0, 0.0, 1.0]);
+    const CHANNEL_MAX: f32 = 1.0;
+    fn to_f32(&self) -> [f32; 4] {
+        self.0
+    }
+    fn from_f32(channels: [f32; 4]) -> Self {
+        Rgba(channels)
+    }
+}
+/// the single channel of `--scalar`, sampled as gray
+impl SamplePixel for Luma<f32> {
+    const FALLBACK: Self = Luma([0.0]);
+    const CHANNEL_MAX: f32 = 1.0;
+    fn to_f32(&self) -> [f32; 4] {
+        [self.0[0], self.0[0], self.0[0], 1.0]
+    }
+    fn from_f32(channels: [f32; 4]) -> Self {
+        Luma([channels[0]])
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMapping {
+    None,
+    Reinhard,
+    Aces,
+    /// John Hable's filmic curve from Uncharted 2
+    Uncharted2,
+}
+impl ToneMapping {
+    /// tone map a hdr pixel, `None` only clamps
+    pub fn map_rgb(&self, color: Rgb<f32>, exposure: f32) -> Rgba<u8> {
+        self.map_rgb_dithered(color, exposure, 0.0)
+    }
+    /// tone map a hdr pixel, alpha is kept as is
+    pub fn map_rgba(&self, color: Rgba<f32>, exposure: f32) -> Rgba<u8> {
+        self.map_rgba_dithered(color, exposure, 0.0)
+    }
+    /// same as `map_rgb`, `dither` (in 8-bit steps, like `bayer_dither`) is added to the
+    /// channels before they are rounded
+    pub fn map_rgb_dithered(&self, color: Rgb<f32>, exposure: f32, dither: f32) -> Rgba<u8> {
+        let [r, g, b] = color
+            .0
+            .map(|c| encode_channel_dithered(self.curve(c * exposure), dither));
+        Rgba([r, g, b, u8::MAX])
+    }
+    /// same as `map_rgba`, alpha isn't dithered
+    pub fn map_rgba_dithered(&self, color: Rgba<f32>, exposure: f32, dither: f32) -> Rgba<u8> {
+        let [r, g, b, a] = color.0;
+        let mut p = self.map_rgb_dithered(Rgb([r, g, b]), exposure, dither);
+        p.0[3] = encode_alpha(a);
+        p
+    }
+    /// the operator's curve of an exposed linear channel, `None` is the identity
+    fn curve(&self, c: f32) -> f32 {
+        match self {
+            Self::None => c,
+            Self::Reinhard => c / (1.0 + c),
+            // Krzysztof Narkowicz's ACES filmic curve approximation
+            Self::Aces => (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14),
+            // John Hable's Uncharted 2 filmic curve, normalized so the white point maps to 1
+            Self::Uncharted2 => {
+                const WHITE: f32 = 11.2;
+                fn curve(x: f32) -> f32 {
+                    const A: f32 = 0.15; // shoulder strength
+                    const B: f32 = 0.50; // linear strength
+                    const C: f32 = 0.10; // linear angle
+                    const D: f32 = 0.20; // toe strength
+                    const E: f32 = 0.02; // toe numerator
+                    const F: f32 = 0.30; // toe denominator
+                    (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+                }
+                curve(c) / curve(WHITE)
+            }
+        }
+    }
+}
+
+pub fn reinhard_tone_mapping_rgb(color: Rgb<f32>, exposure: f32) -> Rgba<u8> {
+    ToneMapping::Reinhard.map_rgb(color, exposure)
+}
+pub fn reinhard_tone_mapping_rgba(color: Rgba<f32>, exposure: f32) -> Rgba<u8> {
+    ToneMapping::Reinhard.map_rgba(color, exposure)
+}
+
+/// Krzysztof Narkowicz's ACES filmic curve approximation
+pub fn aces_tone_mapping_rgb(color: Rgb<f32>, exposure: f32) -> Rgba<u8> {
+    ToneMapping::Aces.map_rgb(color, exposure)
+}
+pub fn aces_tone_mapping_rgba(color: Rgba<f32>, exposure: f32) -> Rgba<u8> {
+    ToneMapping::Aces.map_rgba(color, exposure)
+}
+
+/// John Hable's Uncharted 2 filmic curve, normalized so the white point maps to 1
+pub fn uncharted2_tone_mapping_rgb(color: Rgb<f32>, exposure: f32) -> Rgba<u8> {
+    ToneMapping::Uncharted2.map_rgb(color, exposure)
+}
+pub fn uncharted2_tone_mapping_rgba(color: Rgba<f32>, exposure: f32) -> Rgba<u8> {
+    ToneMapping::Uncharted2.map_rgba(color, exposure)
+}
+
+/// log-average luminance of an equirect image, each row is weighted by the solid angle it covers
+/// so the stretched poles don't dominate, non-float images are read as linear in [0, 1]
+pub fn average_luminance(img: &DynamicImage) -> f32 {
+    use crate::par::*;
+    use std::f32::consts::PI;
+    // keeps ln finite for black pixels
+    const DELTA: f32 = 1e-4;
+
+    let rgb;
+    let rgb = match img {
+        DynamicImage::ImageRgb32F(img) => img,
+        _ => {
+            rgb = img.to_rgb32f();
+            &rgb
+        }
+    };
+    let (width, height) = rgb.dimensions();
+    if width == 0 || height == 0 {
+        return 0.0;
+    }
+    let (log_sum, weight_sum) = rgb
+        .par_chunks(width as usize * 3)
+        .enumerate()
+        .map(|(y, row)| {
+            let weight = ((y as f32 + 0.5) / height as f32 * PI - PI / 2.0).cos();
+            let log_sum: f32 = row
+                .chunks_exact(3)
+                .map(|c| {
+                    (DELTA + 0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2])
+                        .max(DELTA)
+                        .ln()
+                })
+                .sum();
+            (log_sum * weight, width as f32 * weight)
+        })
+        .reduce(|| (0.0, 0.0), |a, b| (a.0 + b.0, a.1 + b.1));
+    (log_sum / weight_sum).exp()
+}
+
+/// range of the log2 luminance (stops) counted by `luminance_histogram` for float images,
+/// luminance outside of it is counted in the first or last bin
+pub const HISTOGRAM_LOG2_RANGE: (f32, f32) = (-12.0, 12.0);
+
+/// pixel count of each of `bins` luminance ranges, split evenly over `HISTOGRAM_LOG2_RANGE` in
+/// log space for float images and over [0, 1] for others, which are read as linear
+pub fn luminance_histogram(img: &DynamicImage, bins: usize) -> Vec<u64> {
+    use crate::par::*;
+
+    let log = matches!(
+        img,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+    let rgb;
+    let rgb = match img {
+        DynamicImage::ImageRgb32F(img) => img,
+        _ => {
+            rgb = img.to_rgb32f();
+            &rgb
+        }
+    };
+    let (low, high) = if log {
+        HISTOGRAM_LOG2_RANGE
+    } else {
+        (0.0, 1.0)
+    };
+    let bin = |c: &[f32]| {
+        let luminance = 0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2];
+        let value = if log { luminance.log2() } else { luminance };
+        // NaN and -inf of black pixels go to the first bin
+        let t = ((value - low) / (high - low) * bins as f32).max(0.0);
+        (t as usize).min(bins - 1)
+    };
+    if bins == 0 {
+        return Vec::new();
+    }
+    rgb.par_chunks(3 * 1024)
+        .fold(
+            || vec![0; bins],
+            |mut counts, chunk| {
+                for c in chunk.chunks_exact(3) {
+                    counts[bin(c)] += 1;
+                }
+                counts
+            },
+        )
+        .reduce(
+            || vec![0; bins],
+            |mut a, b| {
+                for (a, b) in a.iter_mut().zip(b) {
+                    *a += b;
+                }
+                a
+            },
+        )
+}
+
+/// luminance of the pixels of a face in units of full intensity, of the channel values as stored
+/// (srgb encoded for srgb faces)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FaceStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    /// fraction of the pixels with a red, green or blue channel at or above full intensity
+    pub clipped: f32,
+}
+
+/// the `FaceStats` of an image, all zero for an empty one
+pub fn face_stats<P: SamplePixel>(img: &impl GenericImageView<Pixel = P>) -> FaceStats {
+    let (mut min, mut max, mut sum, mut clipped) = (f32::INFINITY, f32::NEG_INFINITY, 0.0, 0);
+    for (_, _, pixel) in img.pixels() {
+        let [r, g, b, _] = pixel.to_f32().map(|c| c / P::CHANNEL_MAX);
+        let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        min = min.min(luminance);
+        max = max.max(luminance);
+        sum += luminance as f64;
+        if r >= 1.0 || g >= 1.0 || b >= 1.0 {
+            clipped += 1;
+        }
+    }
+    let count = img.width() as u64 * img.height() as u64;
+    if count == 0 {
+        return FaceStats {
+            min: 0.0,
+            max: 0.0,
+            mean: 0.0,
+            clipped: 0.0,
+        };
+    }
+    FaceStats {
+        min,
+        max,
+        mean: (sum / count as f64) as f32,
+        clipped: (clipped as f64 / count as f64) as f32,
+    }
+}
+
+/// decode an srgb channel in [0, 1] to linear light
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+/// encode a linear light channel in [0, 1] to srgb
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// gamma encode a linear channel in [0, 1] to 8 bits
+fn encode_channel_dithered(c: f32, dither: f32) -> u8 {
+    (c.clamp(0.0, 1.0).powf(1.0 / 2.2) * 255.0 + dither)
+        .round()
+        .clamp(0.0, 255.0) as u8
+}
+fn encode_alpha(a: f32) -> u8 {
+    (a.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// offset (in steps of the quantized channels, within -0.5 and 0.5) of an 8x8 ordered (bayer)
+/// dither at pixel `(x, y)`, added before rounding it breaks the bands of smooth gradients up
+/// into a fine pattern of the same average
+pub fn bayer_dither(x: u32, y: u32) -> f32 {
+    const BAYER: [[u8; 8]; 8] = [
+        [0, 32, 8, 40, 2, 34, 10, 42],
+        [48, 16, 56, 24, 50, 18, 58, 26],
+        [12, 44, 4, 36, 14, 46, 6, 38],
+        [60, 28, 52, 20, 62, 30, 54, 22],
+        [3, 35, 11, 43, 1, 33, 9, 41],
+        [51, 19, 59, 27, 49, 17, 57, 25],
+        [15, 47, 7, 39, 13, 45, 5, 37],
+        [63, 31, 55, 23, 61, 29, 53, 21],
+    ];
+    (BAYER[y as usize % 8][x as usize % 8] as f32 + 0.5) / 64.0 - 0.5
+}
+
+/// equiangular cubemap warp, maps an evenly spaced (in angle) face coordinate in [0, 1]
+/// to the standard cubemap face coordinate in [0, 1]
+pub fn eac_warp(u: f32) -> f32 {
+    use std::f32::consts::FRAC_PI_4;
+
+    ((FRAC_PI_4 * (2.0 * u - 1.0)).tan() + 1.0) / 2.0
+}
+
+/// rotation matrix from angles in radians, applied in the order yaw (about z, the pole axis),
+/// then pitch (about the rotated y), then roll (about the rotated x, the front axis)
+pub fn rotation_matrix(yaw: f32, pitch: f32, roll: f32) -> [[f32; 3]; 3] {
+    let (sy, cy) = yaw.sin_cos();
+    let (sp, cp) = pitch.sin_cos();
+    let (sr, cr) = roll.sin_cos();
+    // Rz(yaw) * Ry(pitch) * Rx(roll)
+    [
+        [cy * cp, cy * sp * sr - sy * cr, cy * sp * cr + sy * sr],
+        [sy * cp, sy * sp * sr + cy * cr, sy * sp * cr - cy * sr],
+        [-sp, cp * sr, cp * cr],
+    ]
+}
+
+/// the matrix applying `b` and then `a`
+pub fn matrix_product(a: &[[f32; 3]; 3], b: &[[f32; 3]; 3]) -> [[f32; 3]; 3] {
+    std::array::from_fn(|i| std::array::from_fn(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Vector3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vector3 {
+    pub fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+    /// return a normalized version of this vector
+    pub fn normalize(&self) -> Self {
+        let len = self.len();
+        Self {
+            x: self.x / len,
+            y: self.y / len,
+            z: self.z / len,
+        }
+    }
+    /// same as `normalize` with one reciprocal square root and three multiplications instead of
+    /// three divisions, may differ from it in the last bit
+    pub fn normalize_fast(&self) -> Self {
+        self.scale(1.0 / self.len_squared().sqrt())
+    }
+    /// make this vector3 normalized
+    // pub fn normalize_mut(&mut self) {
+    //     let len = self.len();
+    //     self.x /= len;
+    //     self.y /= len;
+    //     self.z /= len;
+    // }
+    /// multiply this vector by a rotation matrix
+    pub fn rotate(&self, m: &[[f32; 3]; 3]) -> Self {
+        Self {
+            x: m[0][0] * self.x + m[0][1] * self.y + m[0][2] * self.z,
+            y: m[1][0] * self.x + m[1][1] * self.y + m[1][2] * self.z,
+            z: m[2][0] * self.x + m[2][1] * self.y + m[2][2] * self.z,
+        }
+    }
+    pub fn len(&self) -> f32 {
+        self.len_squared().sqrt()
+    }
+    pub fn len_squared(&self) -> f32 {
+        self.x * self.x + self.y * self.y + self.z * self.z
+    }
+    pub fn dot(&self, o: &Vector3) -> f32 {
+        self.x * o.x + self.y * o.y + self.z * o.z
+    }
+    /// right-handed cross product, perpendicular to both vectors
+    pub fn cross(&self, o: &Vector3) -> Vector3 {
+        Self {
+            x: self.y * o.z - self.z * o.y,
+            y: self.z * o.x - self.x * o.z,
+            z: self.x * o.y - self.y * o.x,
+        }
+    }
+    pub fn add(&self, o: &Vector3) -> Vector3 {
+        Self {
+            x: self.x + o.x,
+            y: self.y + o.y,
+            z: self.z + o.z,
+        }
+    }
+    pub fn sub(&self, o: &Vector3) -> Vector3 {
+        Self {
+            x: self.x - o.x,
+            y: self.y - o.y,
+            z: self.z - o.z,
+        }
+    }
+    pub fn scale(&self, s: f32) -> Vector3 {
+        Self {
+            x: self.x * s,
+            y: self.y * s,
+            z: self.z * s,
+        }
+    }
+}
+
+impl std::ops::Add for Vector3 {
+    type Output = Vector3;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Vector3::add(&self, &rhs)
+    }
+}
+
+impl std::ops::Sub for Vector3 {
+    type Output = Vector3;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Vector3::sub(&self, &rhs)
+    }
+}
+
+impl std::ops::Mul<f32> for Vector3 {
+    type Output = Vector3;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        self.scale(rhs)
+    }
+}
@@ -0,0 +1,138 @@
+use std::io::{BufRead, Seek};
+
+use image::io::Reader;
+use image::{DynamicImage, ImageDecoder, ImageResult};
+
+use crate::{ColorSpace, OutputFormat};
+
+/// decode an image along with its icc profile, if it has one
+pub fn decode_with_icc<R: BufRead + Seek>(
+    reader: Reader<R>,
+) -> ImageResult<(DynamicImage, Option<Vec<u8>>)> {
+    let mut decoder = reader.with_guessed_format()?.into_decoder()?;
+    // a broken profile shouldn't stop the conversion, the faces are just saved without it
+    let icc = decoder.icc_profile().ok().flatten();
+    Ok((DynamicImage::from_decoder(decoder)?, icc))
+}
+
+/// embed an icc profile into an encoded png, jpg or webp image, other formats are returned as is
+pub fn embed_icc_profile(encoded: Vec<u8>, format: OutputFormat, icc: &[u8]) -> Vec<u8> {
+    match format {
+        OutputFormat::Png => embed_png(encoded, icc),
+        OutputFormat::Jpg => embed_jpg(encoded, icc),
+        OutputFormat::Webp => embed_webp(encoded, icc),
+        _ => encoded,
+    }
+}
+
+/// add an `iCCP` chunk right after `IHDR`
+fn embed_png(encoded: Vec<u8>, icc: &[u8]) -> Vec<u8> {
+    let mut data = b"ICC Profile\0\0".to_vec();
+    data.extend(miniz_oxide::deflate::compress_to_vec_zlib(icc, 6));
+    insert_png_chunks(encoded, &[(b"iCCP", &data)])
+}
+
+/// chromaticities (x 100000) of the white point and the red, green and blue primaries of srgb
+/// and bt.709
+const PNG_CHRM: [u32; 8] = [31270, 32900, 64000, 33000, 30000, 60000, 15000, 6000];
+
+/// add the chunks describing `color_space` to an encoded png right after `IHDR`, `sRGB` (with
+/// the perceptual intent), `gAMA` and `cHRM` for srgb, a gamma of 1 and `cHRM` for linear
+pub fn tag_png_color_space(encoded: Vec<u8>, color_space: ColorSpace) -> Vec<u8> {
+    let chrm: Vec<u8> = PNG_CHRM.iter().flat_map(|c| c.to_be_bytes()).collect();
+    match color_space {
+        ColorSpace::Srgb => insert_png_chunks(
+            encoded,
+            &[
+                (b"sRGB", &[0]),
+                (b"gAMA", &45455u32.to_be_bytes()),
+                (b"cHRM", &chrm),
+            ],
+        ),
+        ColorSpace::Linear => insert_png_chunks(
+            encoded,
+            &[(b"gAMA", &100000u32.to_be_bytes()), (b"cHRM", &chrm)],
+        ),
+    }
+}
+
+/// insert `(type, data)` chunks right after `IHDR`, images that aren't png are returned as is
+fn insert_png_chunks(encoded: Vec<u8>, chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    // signature (8) + IHDR length, type, data (13) and crc
+    const AFTER_IHDR: usize = 8 + 4 + 4 + 13 + 4;
+    if encoded.len() < AFTER_IHDR || &encoded[12..16] != b"IHDR" {
+        return encoded;
+    }
+    let mut inserted = Vec::new();
+    for (kind, data) in chunks {
+        let start = inserted.len();
+        inserted.extend((data.len() as u32).to_be_bytes());
+        inserted.extend(*kind);
+        inserted.extend(*data);
+        inserted.extend(crc32fast::hash(&inserted[start + 4..]).to_be_bytes());
+    }
+    let mut out = encoded;
+    out.splice(AFTER_IHDR..AFTER_IHDR, inserted);
+    out
+}
+
+/// add `APP2` `ICC_PROFILE` segments after `SOI` and the `APP0` jfif header
+fn embed_jpg(encoded: Vec<u8>, icc: &[u8]) -> Vec<u8> {
+    // segment length is 16 bits and includes itself, the tag and the 2 sequence bytes
+    const MAX_CHUNK: usize = u16::MAX as usize - 2 - 12 - 2;
+    if encoded.len() < 4 || encoded[..2] != [0xFF, 0xD8] {
+        return encoded;
+    }
+    let mut at = 2;
+    if encoded[2..4] == [0xFF, 0xE0] && encoded.len() >= 6 {
+        at += 2 + u16::from_be_bytes([encoded[4], encoded[5]]) as usize;
+    }
+    let chunks: Vec<&[u8]> = icc.chunks(MAX_CHUNK).collect();
+    if chunks.len() > u8::MAX as usize || at > encoded.len() {
+        return encoded;
+    }
+    let mut segments = Vec::with_capacity(icc.len() + chunks.len() * 18);
+    for (i, chunk) in chunks.iter().enumerate() {
+        segments.extend([0xFF, 0xE2]);
+        segments.extend(((chunk.len() + 2 + 12 + 2) as u16).to_be_bytes());
+        segments.extend(b"ICC_PROFILE\0");
+        segments.extend([i as u8 + 1, chunks.len() as u8]);
+        segments.extend(*chunk);
+    }
+    let mut out = encoded;
+    out.splice(at..at, segments);
+    out
+}
+
+/// turn a simple `VP8L` webp into the extended format with an `ICCP` chunk
+fn embed_webp(encoded: Vec<u8>, icc: &[u8]) -> Vec<u8> {
+    // RIFF header (12) + VP8L chunk header (8) + signature byte and 28 bits of dimensions
+    if encoded.len() < 25 || &encoded[..4] != b"RIFF" || &encoded[12..16] != b"VP8L" {
+        return encoded;
+    }
+    let bits = u32::from_le_bytes([encoded[21], encoded[22], encoded[23], encoded[24]]);
+    let width = (bits & 0x3FFF) + 1;
+    let height = ((bits >> 14) & 0x3FFF) + 1;
+    let has_alpha = bits >> 28 & 1 == 1;
+    let mut flags = 1 << 5;
+    if has_alpha {
+        flags |= 1 << 4;
+    }
+    let mut out = Vec::with_capacity(encoded.len() + icc.len() + 40);
+    out.extend(b"RIFF\0\0\0\0WEBP");
+    out.extend(b"VP8X");
+    out.extend(10u32.to_le_bytes());
+    out.extend([flags, 0, 0, 0]);
+    out.extend(&(width - 1).to_le_bytes()[..3]);
+    out.extend(&(height - 1).to_le_bytes()[..3]);
+    out.extend(b"ICCP");
+    out.extend((icc.len() as u32).to_le_bytes());
+    out.extend(icc);
+    if icc.len() % 2 == 1 {
+        out.push(0);
+    }
+    out.extend(&encoded[12..]);
+    let riff_size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    out
+}
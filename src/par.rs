@@ -0,0 +1,111 @@
+//! the parallel iterators of rayon with the `parallel` feature, without it sequential std
+//! iterators with the same methods for targets without threads like `wasm32-unknown-unknown`
+
+#[cfg(feature = "parallel")]
+pub use rayon::prelude::*;
+
+#[cfg(not(feature = "parallel"))]
+pub use sequential::*;
+
+#[cfg(not(feature = "parallel"))]
+mod sequential {
+    use image::buffer::{Pixels, PixelsMut};
+    use image::{ImageBuffer, Pixel};
+    use std::iter::{Enumerate, Map, Once, Zip};
+    use std::slice::{Chunks, ChunksMut, Iter, IterMut};
+
+    /// a std iterator with the rayon adapters whose signatures differ from `Iterator`'s
+    pub struct Seq<I>(I);
+
+    impl<I: Iterator> Iterator for Seq<I> {
+        type Item = I::Item;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.0.next()
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            self.0.size_hint()
+        }
+    }
+
+    impl<I: Iterator> Seq<I> {
+        pub fn map<B, F: FnMut(I::Item) -> B>(self, f: F) -> Seq<Map<I, F>> {
+            Seq(self.0.map(f))
+        }
+
+        pub fn enumerate(self) -> Seq<Enumerate<I>> {
+            Seq(self.0.enumerate())
+        }
+
+        pub fn zip<J: IntoIterator>(self, other: J) -> Seq<Zip<I, J::IntoIter>> {
+            Seq(self.0.zip(other))
+        }
+
+        /// splitting hint of rayon, there is nothing to split
+        pub fn with_min_len(self, _min: usize) -> Self {
+            self
+        }
+
+        /// one fold over all items, rayon yields one per split
+        pub fn fold<T, F>(self, identity: impl Fn() -> T, f: F) -> Seq<Once<T>>
+        where
+            F: FnMut(T, I::Item) -> T,
+        {
+            Seq(std::iter::once(self.0.fold(identity(), f)))
+        }
+
+        pub fn reduce<F>(self, identity: impl Fn() -> I::Item, f: F) -> I::Item
+        where
+            F: FnMut(I::Item, I::Item) -> I::Item,
+        {
+            self.0.fold(identity(), f)
+        }
+    }
+
+    pub trait IntoParallelIterator: IntoIterator + Sized {
+        fn into_par_iter(self) -> Seq<Self::IntoIter> {
+            Seq(self.into_iter())
+        }
+    }
+    impl<I: IntoIterator> IntoParallelIterator for I {}
+
+    pub trait ParallelSlice<T> {
+        fn par_iter(&self) -> Seq<Iter<'_, T>>;
+        fn par_iter_mut(&mut self) -> Seq<IterMut<'_, T>>;
+        fn par_chunks(&self, size: usize) -> Seq<Chunks<'_, T>>;
+        fn par_chunks_mut(&mut self, size: usize) -> Seq<ChunksMut<'_, T>>;
+    }
+    impl<T> ParallelSlice<T> for [T] {
+        fn par_iter(&self) -> Seq<Iter<'_, T>> {
+            Seq(self.iter())
+        }
+
+        fn par_iter_mut(&mut self) -> Seq<IterMut<'_, T>> {
+            Seq(self.iter_mut())
+        }
+
+        fn par_chunks(&self, size: usize) -> Seq<Chunks<'_, T>> {
+            Seq(self.chunks(size))
+        }
+
+        fn par_chunks_mut(&mut self, size: usize) -> Seq<ChunksMut<'_, T>> {
+            Seq(self.chunks_mut(size))
+        }
+    }
+
+    /// the pixel iterators image provides with its `rayon` feature
+    pub trait ParallelPixels<P: Pixel> {
+        fn par_pixels(&self) -> Seq<Pixels<'_, P>>;
+        fn par_pixels_mut(&mut self) -> Seq<PixelsMut<'_, P>>;
+    }
+    impl<P: Pixel> ParallelPixels<P> for ImageBuffer<P, Vec<P::Subpixel>> {
+        fn par_pixels(&self) -> Seq<Pixels<'_, P>> {
+            Seq(self.pixels())
+        }
+
+        fn par_pixels_mut(&mut self) -> Seq<PixelsMut<'_, P>> {
+            Seq(self.pixels_mut())
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! single channel float faces of `--scalar`, for fields like depth that aren't colors
+
+use anyhow::{bail, Result};
+use image::io::Reader;
+use image::{DynamicImage, ImageFormat, Luma, Rgba, Rgba32FImage};
+use std::io::{BufRead, Seek, SeekFrom, Write};
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::encoder::{colortype::Gray32Float, TiffEncoder};
+use tiff::ColorType;
+
+use crate::exr_layers::read_exr_unnamed_layer;
+use crate::metadata::decode_with_icc;
+use crate::{FaceBuffer, OutputFormat};
+
+/// a single channel float image
+pub type ScalarImage = FaceBuffer<Luma<f32>>;
+
+/// the first channel of an input image as floats, 8 and 16-bit channels are scaled to 0 to 1
+/// without decoding srgb
+pub fn to_scalar(img: &DynamicImage) -> ScalarImage {
+    let rgba = img.to_rgba32f();
+    let data = rgba.pixels().map(|p| p.0[0]).collect();
+    ScalarImage::from_raw(img.width(), img.height(), data).expect("a value per pixel")
+}
+
+/// decode an input of `--scalar` like `decode_with_icc`, except that exr images are read with
+/// the exr crate and gray float tiff images with the tiff crate, the image crate decodes neither
+/// of them, their single channel becomes a gray `Rgba32F` image
+pub fn decode_scalar<R: BufRead + Seek>(
+    reader: Reader<R>,
+) -> Result<(DynamicImage, Option<Vec<u8>>)> {
+    let reader = reader.with_guessed_format()?;
+    match reader.format() {
+        Some(ImageFormat::OpenExr) => Ok((read_exr_unnamed_layer(reader.into_inner())?, None)),
+        Some(ImageFormat::Tiff) => {
+            let mut inner = reader.into_inner();
+            let start = inner.stream_position()?;
+            let mut decoder = Decoder::new(&mut inner)?;
+            if decoder.colortype()? == ColorType::Gray(32) {
+                let (width, height) = decoder.dimensions()?;
+                if let DecodingResult::F32(data) = decoder.read_image()? {
+                    let img = Rgba32FImage::from_fn(width, height, |x, y| {
+                        let c = data[(y * width + x) as usize];
+                        Rgba([c, c, c, 1.0])
+                    });
+                    return Ok((DynamicImage::ImageRgba32F(img), None));
+                }
+            }
+            // integer tiffs are left to the image crate
+            inner.seek(SeekFrom::Start(start))?;
+            Ok(decode_with_icc(Reader::with_format(
+                inner,
+                ImageFormat::Tiff,
+            ))?)
+        }
+        _ => Ok(decode_with_icc(reader)?),
+    }
+}
+
+/// write a scalar image as a one channel (`Y`) exr or a gray float tiff image
+pub fn write_scalar_image<W: Write + Seek>(
+    img: &ScalarImage,
+    writer: &mut W,
+    format: OutputFormat,
+) -> Result<()> {
+    let (width, height) = img.dimensions();
+    match format {
+        OutputFormat::Exr => {
+            use exr::prelude::*;
+            let channel = AnyChannel::new("Y", FlatSamples::F32(img.as_raw().clone()));
+            let channels = AnyChannels::sort(SmallVec::from_vec(vec![channel]));
+            let layer = Layer::new(
+                (width as usize, height as usize),
+                LayerAttributes::default(),
+                Encoding::default(),
+                channels,
+            );
+            Image::from_layer(layer).write().to_buffered(writer)?;
+        }
+        OutputFormat::Tiff => {
+            TiffEncoder::new(writer)?.write_image::<Gray32Float>(width, height, img.as_raw())?;
+        }
+        _ => bail!("Scalar faces are saved as exr or tiff, not {}.", format),
+    }
+    Ok(())
+}
@@ -0,0 +1,153 @@
+//! bilinear sampling of four uvs at a time with sse2 (always there on x86_64), behind the
+//! `simd` feature
+
+use image::GenericImageView;
+use num_traits::NumCast;
+use std::arch::x86_64::*;
+
+use crate::math::{AddressMode, Addressing, SamplePixel};
+
+/// the bilinear samples of `uvs` into `out`, the same as `sample_separable` with the tent
+/// kernel down to the last bit, `None` where v is outside [0, 1]
+pub fn sample_bilinear<P: SamplePixel>(
+    img: &impl GenericImageView<Pixel = P>,
+    uvs: &[(f32, f32)],
+    addressing: Addressing,
+    out: &mut [Option<P>],
+) {
+    let (w, h) = img.dimensions();
+    if w == 0 || h == 0 {
+        out.fill(None);
+        return;
+    }
+    let mut uv_chunks = uvs.chunks_exact(4);
+    let mut out_chunks = out.chunks_exact_mut(4);
+    for (uvs, out) in (&mut uv_chunks).zip(&mut out_chunks) {
+        // SAFETY: sse2 is part of x86_64 and `sample4` only reads texels `texel_pair` resolved
+        // into the image, which isn't empty
+        unsafe { sample4(img, uvs, addressing, out) };
+    }
+    // the last up to three uvs are padded with a copy of the first
+    let (uvs, out) = (uv_chunks.remainder(), out_chunks.into_remainder());
+    if let Some(first) = uvs.first() {
+        let mut padded = [*first; 4];
+        padded[..uvs.len()].copy_from_slice(uvs);
+        let mut samples = [None; 4];
+        // SAFETY: as above, the padding gives `sample4` the four uvs and outputs it reads
+        unsafe { sample4(img, &padded, addressing, &mut samples) };
+        out.copy_from_slice(&samples[..out.len()]);
+    }
+}
+
+/// the two texels a tent filter at `i + fraction` reads on an axis `size` texels long
+fn texel_pair(mode: AddressMode, i: i32, size: u32) -> (u32, u32) {
+    if i >= 0 && i + 1 < size as i32 {
+        (i as u32, i as u32 + 1)
+    } else {
+        (
+            mode.resolve(i as i64, size),
+            mode.resolve(i as i64 + 1, size),
+        )
+    }
+}
+
+/// the bilinear samples of four uvs into the first four of `out`
+///
+/// # Safety
+///
+/// sse2 has to be available and `img` can't be empty, the texels are read unchecked
+unsafe fn sample4<P: SamplePixel>(
+    img: &impl GenericImageView<Pixel = P>,
+    uvs: &[(f32, f32)],
+    addressing: Addressing,
+    out: &mut [Option<P>],
+) {
+    let (w, h) = img.dimensions();
+    let u = _mm_setr_ps(uvs[0].0, uvs[1].0, uvs[2].0, uvs[3].0);
+    let v = _mm_setr_ps(uvs[0].1, uvs[1].1, uvs[2].1, uvs[3].1);
+    // NaN fails both compares
+    let valid = _mm_movemask_ps(_mm_and_ps(
+        _mm_cmpge_ps(v, _mm_setzero_ps()),
+        _mm_cmple_ps(v, _mm_set1_ps(1.0)),
+    ));
+    let half = _mm_set1_ps(0.5);
+    let x = _mm_sub_ps(_mm_mul_ps(u, _mm_set1_ps(w as f32)), half);
+    let y = _mm_sub_ps(_mm_mul_ps(v, _mm_set1_ps(h as f32)), half);
+    let (x0, fx) = floor(x);
+    let (y0, fy) = floor(y);
+    // the weights of the two columns and rows as `sample_separable` computes them, `x - x0` is
+    // the fraction and `x - (x0 + 1)` the distance to the next column
+    let one = _mm_set1_ps(1.0);
+    let next = |x: __m128, i0: __m128i| _mm_sub_ps(x, _mm_add_ps(_mm_cvtepi32_ps(i0), one));
+    let mut x0s = [0i32; 4];
+    let mut y0s = [0i32; 4];
+    let mut wxs = [[0.0f32; 4]; 2];
+    let mut wys = [[0.0f32; 4]; 2];
+    _mm_storeu_si128(x0s.as_mut_ptr().cast(), x0);
+    _mm_storeu_si128(y0s.as_mut_ptr().cast(), y0);
+    _mm_storeu_ps(wxs[0].as_mut_ptr(), tent(fx));
+    _mm_storeu_ps(wxs[1].as_mut_ptr(), tent(next(x, x0)));
+    _mm_storeu_ps(wys[0].as_mut_ptr(), tent(fy));
+    _mm_storeu_ps(wys[1].as_mut_ptr(), tent(next(y, y0)));
+    let (zero, max) = (_mm_setzero_ps(), _mm_set1_ps(P::CHANNEL_MAX));
+    for lane in 0..4 {
+        if valid & (1 << lane) == 0 {
+            out[lane] = None;
+            continue;
+        }
+        let (c0, c1) = texel_pair(addressing.u, x0s[lane], w);
+        let (r0, r1) = texel_pair(addressing.v, y0s[lane], h);
+        // summed in the order of `sample_separable`, row by row, so the result rounds the same
+        let mut sum = _mm_setzero_ps();
+        let mut weight_sum = 0.0;
+        for (row, wy) in [r0, r1].into_iter().zip([wys[0][lane], wys[1][lane]]) {
+            for (column, wx) in [c0, c1].into_iter().zip([wxs[0][lane], wxs[1][lane]]) {
+                let weight = wy * wx;
+                let c = img.unsafe_get_pixel(column, row).to_f32();
+                sum = _mm_add_ps(
+                    sum,
+                    _mm_mul_ps(_mm_loadu_ps(c.as_ptr()), _mm_set1_ps(weight)),
+                );
+                weight_sum += weight;
+            }
+        }
+        let blended = _mm_div_ps(sum, _mm_set1_ps(weight_sum));
+        let mut c = [0.0; 4];
+        if P::CHANNEL_MAX > 1.0 && P::CHANNEL_COUNT == 4 {
+            // clamped and rounded half away from zero like `from_f32` does for integer
+            // channels, which calls into libm for each of them
+            let clamped = _mm_min_ps(_mm_max_ps(blended, zero), max);
+            let rounded = _mm_cvtepi32_ps(_mm_cvttps_epi32(_mm_add_ps(clamped, half)));
+            _mm_storeu_ps(c.as_mut_ptr(), rounded);
+            let channels = c.map(|c| NumCast::from(c).expect("a value in the channel range"));
+            out[lane] = Some(*P::from_slice(&channels));
+        } else {
+            _mm_storeu_ps(c.as_mut_ptr(), blended);
+            out[lane] = Some(P::from_f32(c));
+        }
+    }
+}
+
+/// the integer floor of each lane and the fraction above it, sse2 only truncates toward zero
+///
+/// # Safety
+///
+/// sse2 has to be available
+unsafe fn floor(x: __m128) -> (__m128i, __m128) {
+    let truncated = _mm_cvttps_epi32(x);
+    // lanes below their truncation are negative with a fraction, `cmplt` is -1 for them
+    let below = _mm_castps_si128(_mm_cmplt_ps(x, _mm_cvtepi32_ps(truncated)));
+    let floor = _mm_add_epi32(truncated, below);
+    (floor, _mm_sub_ps(x, _mm_cvtepi32_ps(floor)))
+}
+
+/// the tent kernel `(1 - |d|).max(0)` of `sample_separable` in each lane, NaN lanes are 0
+/// like with `f32::max`
+///
+/// # Safety
+///
+/// sse2 has to be available
+unsafe fn tent(d: __m128) -> __m128 {
+    let abs = _mm_andnot_ps(_mm_set1_ps(-0.0), d);
+    _mm_max_ps(_mm_sub_ps(_mm_set1_ps(1.0), abs), _mm_setzero_ps())
+}
@@ -0,0 +1,1049 @@
+//! tests of the conversion, sampling and output helpers
+
+use crate::dds::DDS_FACE_ORDER;
+use crate::ktx2::{write_ktx2_cubemap, KTX2_FACE_ORDER, KTX2_PREFIX_SIZE};
+use crate::math::{
+    uncharted2_tone_mapping_rgb, Addressing, Interpolation, SamplePixel, SphericalAngle,
+    ToneMapping, Vector3,
+};
+use crate::scalar::{to_scalar, write_scalar_image, ScalarImage};
+use crate::*;
+use clap::{Parser, ValueEnum};
+use image::{Luma, Rgba32FImage};
+use std::io::Cursor;
+
+#[test]
+fn face_file_names() {
+    use NamingScheme::*;
+    // sides in the order of `Side::all`: front, back, left, right, top, bottom
+    let table = [
+        (Compass, ["front", "back", "left", "right", "top", "bottom"]),
+        (Axis, ["posz", "negz", "negx", "posx", "posy", "negy"]),
+        (Unity, ["front", "back", "left", "right", "up", "down"]),
+        (Opengl, ["pz", "nz", "nx", "px", "py", "ny"]),
+    ];
+    for (scheme, names) in table {
+        for (side, name) in Side::all().into_iter().zip(names) {
+            assert_eq!(filename(side, scheme), name, "{:?} {}", scheme, side);
+        }
+    }
+    // the axis names follow the face order of dds cubemaps: +x, -x, +y, -y, +z, -z
+    let dds_names: Vec<_> = DDS_FACE_ORDER
+        .iter()
+        .map(|side| filename(*side, Axis))
+        .collect();
+    assert_eq!(dds_names, ["posx", "negx", "posy", "negy", "posz", "negz"]);
+}
+
+/// a 16-bit image, enough precision for the directions of `direction_panorama`
+type DirectionImage = ImageBuffer<Rgba<u16>, Vec<u16>>;
+
+/// a 2:1 panorama whose pixels are the directions they show, as `dir * 0.5 + 0.5` in the frame
+/// of the faces: x front, y at the longitude right of the front and z down
+fn direction_panorama(width: u32) -> DirectionImage {
+    let height = width / 2;
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let u = (x as f32 + 0.5) / width as f32;
+        let v = (y as f32 + 0.5) / height as f32;
+        let theta = (u - 0.5) * std::f32::consts::TAU;
+        let latitude = (0.5 - v) * std::f32::consts::PI;
+        let dir = [
+            latitude.cos() * theta.cos(),
+            latitude.cos() * theta.sin(),
+            -latitude.sin(),
+        ];
+        let [r, g, b] = dir.map(|c| ((c * 0.5 + 0.5) * u16::MAX as f32).round() as u16);
+        Rgba([r, g, b, u16::MAX])
+    })
+}
+
+/// the unit direction a texel of a face rendered from `direction_panorama` shows
+fn shown_direction(img: &DirectionImage, x: u32, y: u32) -> Vector3 {
+    let [r, g, b, _] = img
+        .get_pixel(x, y)
+        .0
+        .map(|c| c as f32 / u16::MAX as f32 * 2.0 - 1.0);
+    Vector3::new(r, g, b).normalize()
+}
+
+fn angle_between(a: Vector3, b: Vector3) -> f32 {
+    a.dot(&b).clamp(-1.0, 1.0).acos()
+}
+
+#[test]
+fn cross_seams_are_continuous() {
+    #[derive(Clone, Copy)]
+    enum Edge {
+        Top,
+        Bottom,
+        Left,
+        Right,
+    }
+    const SIZE: u32 = 32;
+    let config = Config {
+        size: FaceSizes([SIZE; 6]),
+        ..Config::parse_from(["equirect2cubemap", "-", "-"])
+    };
+    let faces = convert_view(&config, &direction_panorama(1024));
+    let cross = layout::pack_horizontal_cross(&faces, SIZE);
+    // the texels along an edge of a cell, left to right or top to bottom
+    let edge = |(column, row): (u32, u32), edge: Edge| -> Vec<(u32, u32)> {
+        let (x0, y0, last) = (column * SIZE, row * SIZE, SIZE - 1);
+        (0..SIZE)
+            .map(|i| match edge {
+                Edge::Top => (x0 + i, y0),
+                Edge::Bottom => (x0 + i, y0 + last),
+                Edge::Left => (x0, y0 + i),
+                Edge::Right => (x0 + last, y0 + i),
+            })
+            .collect()
+    };
+    let (top, right, front, left, back, bottom) = ((1, 0), (0, 1), (1, 1), (2, 1), (3, 1), (1, 2));
+    // the 12 cube edges, the first 5 are between neighboring cells and the other 7 are the
+    // seams the cross is cut open at, `true` if one edge runs the other way
+    let seams = [
+        (right, Edge::Right, front, Edge::Left, false),
+        (front, Edge::Right, left, Edge::Left, false),
+        (left, Edge::Right, back, Edge::Left, false),
+        (top, Edge::Bottom, front, Edge::Top, false),
+        (front, Edge::Bottom, bottom, Edge::Top, false),
+        (back, Edge::Right, right, Edge::Left, false),
+        (top, Edge::Top, back, Edge::Top, true),
+        (top, Edge::Left, right, Edge::Top, false),
+        (top, Edge::Right, left, Edge::Top, true),
+        (bottom, Edge::Left, right, Edge::Bottom, true),
+        (bottom, Edge::Right, left, Edge::Bottom, false),
+        (bottom, Edge::Bottom, back, Edge::Bottom, true),
+    ];
+    // texels on both sides of an edge are a texel (~2.8 degrees) apart
+    let max_angle = 6f32.to_radians();
+    for (i, (a, edge_a, b, edge_b, reversed)) in seams.into_iter().enumerate() {
+        let texels_a = edge(a, edge_a);
+        let mut texels_b = edge(b, edge_b);
+        if reversed {
+            texels_b.reverse();
+        }
+        for ((xa, ya), (xb, yb)) in texels_a.into_iter().zip(texels_b) {
+            let angle = angle_between(
+                shown_direction(&cross, xa, ya),
+                shown_direction(&cross, xb, yb),
+            );
+            assert!(
+                angle < max_angle,
+                "seam {} breaks between ({}, {}) and ({}, {}), {} degrees apart",
+                i,
+                xa,
+                ya,
+                xb,
+                yb,
+                angle.to_degrees()
+            );
+        }
+    }
+}
+
+#[test]
+fn bilinear_wraps_across_the_seam() {
+    // the first column is 0 and the last 1, the ones between 0.25
+    let img: DirectionImage = ImageBuffer::from_fn(4, 2, |x, _| {
+        let c = match x {
+            0 => 0,
+            3 => u16::MAX,
+            _ => u16::MAX / 4,
+        };
+        Rgba([c, c, c, u16::MAX])
+    });
+    let sample =
+        |u: f32| Interpolation::Linear.sample(&img, (u, 0.5)).0[0] as f32 / u16::MAX as f32;
+    // u = 0 and 1 are the edge between the last and the first column, half of each
+    for u in [0.0, 1.0] {
+        assert!((sample(u) - 0.5).abs() < 1e-4, "u = {}", u);
+    }
+    // a tenth of a texel inside each edge
+    assert!((sample(0.1 / 4.0) - 0.4).abs() < 1e-4);
+    assert!((sample(1.0 - 0.1 / 4.0) - 0.6).abs() < 1e-4);
+}
+
+#[test]
+fn poles_have_a_defined_theta() {
+    let size = 64.0;
+    let poles = [
+        Vector3::new(0.0, 0.0, -1.0),
+        Vector3::new(0.0, 0.0, 1.0),
+        Vector3::new(-0.0, -0.0, -1.0),
+        // the centers of the top and bottom faces
+        face_direction(Side::Top, size / 2.0, size / 2.0, size).normalize(),
+        face_direction(Side::Bottom, size / 2.0, size / 2.0, size).normalize(),
+    ];
+    for dir in poles {
+        let angle = SphericalAngle::from_normalized_vector(dir);
+        assert_eq!(angle.theta, 0.0, "{:?}", dir);
+        let (u, v) = angle.to_uv();
+        assert!(u.is_finite() && v.is_finite(), "{:?}", dir);
+        assert!(v == 0.0 || v == 1.0, "{:?}", dir);
+    }
+    // the top face center samples the top row instead of the fallback color
+    let img = ImageBuffer::from_pixel(16, 8, Rgba([127u8, 0, 0, 255]));
+    let top = face_direction(Side::Top, size / 2.0, size / 2.0, size).normalize();
+    let uv = SphericalAngle::from_normalized_vector(top).to_uv();
+    assert_eq!(
+        Interpolation::Linear.sample(&img, uv),
+        Rgba([127, 0, 0, 255])
+    );
+}
+
+fn assert_close(a: Vector3, b: Vector3) {
+    assert!(a.sub(&b).len() < 1e-5, "{:?} != {:?}", a, b);
+}
+
+#[test]
+fn vector_products_and_operators() {
+    let (x, y, z) = (
+        Vector3::new(1.0, 0.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        Vector3::new(0.0, 0.0, 1.0),
+    );
+    // right-handed
+    assert_close(x.cross(&y), z);
+    assert_close(y.cross(&z), x);
+    assert_close(z.cross(&x), y);
+    assert_close(y.cross(&x), z.scale(-1.0));
+    let (a, b) = (Vector3::new(1.0, 2.0, 3.0), Vector3::new(-4.0, 0.5, 2.0));
+    let c = a.cross(&b);
+    assert!(c.dot(&a).abs() < 1e-5 && c.dot(&b).abs() < 1e-5);
+    assert_close(a.cross(&a), Vector3::new(0.0, 0.0, 0.0));
+    assert_eq!(a.dot(&b), -4.0 + 1.0 + 6.0);
+    assert!((a.dot(&a) - a.len() * a.len()).abs() < 1e-5);
+    // operators match the methods and each other
+    assert_close(a + b, a.add(&b));
+    assert_close(a - b, a.sub(&b));
+    assert_close(a * 2.0, a.scale(2.0));
+    assert_close(a + b - b, a);
+    assert_close(a - a, Vector3::new(0.0, 0.0, 0.0));
+    assert_close(a + a, a * 2.0);
+    assert_close(a * 1.0, a);
+    assert_close((a + b) * 3.0, a * 3.0 + b * 3.0);
+}
+
+#[test]
+fn uv_round_trips_through_directions() {
+    for i in 0..16 {
+        for j in 0..8 {
+            let (u, v) = ((i as f32 + 0.5) / 16.0, (j as f32 + 0.5) / 8.0);
+            let dir = SphericalAngle::from_uv(u, v).to_direction();
+            assert!((dir.len() - 1.0).abs() < 1e-5);
+            let (u2, v2) = SphericalAngle::from_normalized_vector(dir).to_uv();
+            assert!(
+                (u2 - u).abs() < 1e-5 && (v2 - v).abs() < 1e-5,
+                "({}, {}) came back as ({}, {})",
+                u,
+                v,
+                u2,
+                v2
+            );
+        }
+    }
+    // any u of the top and bottom rows is the pole, which comes back with theta 0
+    for (v, z) in [(0.0, -1.0), (1.0, 1.0)] {
+        for u in [0.1, 0.5, 0.9] {
+            let dir = SphericalAngle::from_uv(u, v).to_direction();
+            assert_close(dir, Vector3::new(0.0, 0.0, z));
+            let (u2, v2) = SphericalAngle::from_normalized_vector(dir).to_uv();
+            assert_eq!((u2, v2), (0.5, v));
+        }
+    }
+}
+
+/// a png of `width` x `height` palette indices into `palette`, which the image crate decodes but
+/// can't encode, the pixel data is stored in uncompressed deflate blocks
+fn indexed_png(width: u32, height: u32, palette: &[[u8; 3]], index: u8) -> Vec<u8> {
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &b in bytes {
+            crc ^= b as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ 0xedb8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        !crc
+    }
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut chunk = |kind: &[u8; 4], data: &[u8]| {
+        png.extend((data.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend(kind);
+        png.extend(data);
+        png.extend(crc32(&png[start..]).to_be_bytes());
+    };
+    let mut header = Vec::new();
+    header.extend(width.to_be_bytes());
+    header.extend(height.to_be_bytes());
+    // 8 bits per index, color type 3, default compression, filter and no interlacing
+    header.extend([8, 3, 0, 0, 0]);
+    chunk(b"IHDR", &header);
+    chunk(b"PLTE", &palette.concat());
+    // each row starts with filter type 0
+    let mut rows = Vec::new();
+    for _ in 0..height {
+        rows.push(0);
+        rows.extend(std::iter::repeat_n(index, width as usize));
+    }
+    // a zlib header, a single final stored block and the adler32 of the rows
+    let mut zlib = vec![0x78, 0x01, 1];
+    zlib.extend((rows.len() as u16).to_le_bytes());
+    zlib.extend((!(rows.len() as u16)).to_le_bytes());
+    zlib.extend(&rows);
+    let (a, b) = rows.iter().fold((1u32, 0u32), |(a, b), &x| {
+        let a = (a + x as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    zlib.extend(((b << 16) | a).to_be_bytes());
+    chunk(b"IDAT", &zlib);
+    chunk(b"IEND", &[]);
+    png
+}
+
+fn encode_png(img: DynamicImage) -> Vec<u8> {
+    let mut bytes = Cursor::new(Vec::new());
+    img.write_to(&mut bytes, image::ImageFormat::Png)
+        .expect("encodable");
+    bytes.into_inner()
+}
+
+#[test]
+fn grayscale_and_indexed_inputs_become_rgba() {
+    let config = Config {
+        size: FaceSizes([8; 6]),
+        ..Config::parse_from(["equirect2cubemap", "-", "-"])
+    };
+    let gray = encode_png(DynamicImage::ImageLuma8(ImageBuffer::from_pixel(
+        16,
+        8,
+        Luma([100]),
+    )));
+    let indexed = indexed_png(16, 8, &[[0, 0, 0], [10, 200, 30]], 1);
+    for (bytes, expected) in [(gray, [100, 100, 100, 255]), (indexed, [10, 200, 30, 255])] {
+        let img = to_samplable(image::load_from_memory(&bytes).expect("a valid png"));
+        let DynamicImage::ImageRgba8(rgba) = &img else {
+            panic!("expected Rgba8, got {:?}", img.color());
+        };
+        assert!(rgba.pixels().all(|p| p.0 == expected));
+        let faces = convert(&config, &img);
+        assert_eq!(faces.len(), 6);
+        for (face, side) in faces {
+            assert_eq!(face.dimensions(), (8, 8));
+            assert!(face.pixels().all(|p| p.0 == expected), "{}", side);
+        }
+    }
+    let gray16 = encode_png(DynamicImage::ImageLuma16(ImageBuffer::from_pixel(
+        16,
+        8,
+        Luma([40000]),
+    )));
+    let img = to_samplable(image::load_from_memory(&gray16).expect("a valid png"));
+    let DynamicImage::ImageRgba16(rgba) = &img else {
+        panic!("expected Rgba16, got {:?}", img.color());
+    };
+    assert!(rgba.pixels().all(|p| p.0 == [40000, 40000, 40000, 65535]));
+    for (face, side) in convert_view(&config, rgba) {
+        assert!(
+            face.pixels().all(|p| p.0 == [40000, 40000, 40000, 65535]),
+            "{}",
+            side
+        );
+    }
+}
+
+#[test]
+fn failed_samples_get_the_fill_color() {
+    let magenta = Rgba([255, 0, 255, 0]);
+    let config = Config {
+        size: FaceSizes([8; 6]),
+        fill_color: FillColor(magenta.0),
+        ..Config::parse_from(["equirect2cubemap", "-", "-"])
+    };
+    // uvs past the poles and empty sources fail for every filter
+    let img = RgbaImage::from_pixel(16, 8, Rgba([9, 9, 9, 255]));
+    for interpolation in Interpolation::value_variants() {
+        assert_eq!(interpolation.try_sample(&img, (0.5, 1.5)), None);
+        assert_eq!(
+            interpolation.try_sample(&RgbaImage::new(0, 0), (0.5, 0.5)),
+            None
+        );
+    }
+    let empty = DynamicImage::ImageRgba8(RgbaImage::new(0, 0));
+    for (face, side) in convert(&config, &empty) {
+        assert!(face.pixels().all(|p| *p == magenta), "{}", side);
+    }
+}
+
+#[test]
+fn uncharted2_maps_the_white_point_to_white() {
+    for exposure in [0.25, 1.0, 2.0, 8.0] {
+        let white = Rgb([11.2 / exposure; 3]);
+        assert_eq!(
+            uncharted2_tone_mapping_rgb(white, exposure),
+            Rgba([255, 255, 255, 255])
+        );
+    }
+    // Hable's curve at an exposed 1.0 is 0.3043 of the white point's, 148 after the 2.2 gamma
+    assert_eq!(
+        ToneMapping::Uncharted2.map_rgb(Rgb([0.5; 3]), 2.0),
+        Rgba([148, 148, 148, 255])
+    );
+    assert_eq!(
+        ToneMapping::Uncharted2.map_rgb(Rgb([0.0, 0.5, 2.0]), 1.0),
+        Rgba([0, 115, 185, 255])
+    );
+}
+
+#[test]
+fn side_names_round_trip() {
+    for side in Side::all() {
+        assert_eq!(side.to_string().parse::<Side>(), Ok(side));
+    }
+    assert!("Front".parse::<Side>().is_err());
+    assert!("up".parse::<Side>().is_err());
+    assert!("".parse::<Side>().is_err());
+}
+
+#[test]
+fn faces_show_their_directions_unmirrored() {
+    const SIZE: u32 = 32;
+    let config = Config {
+        size: FaceSizes([SIZE; 6]),
+        ..Config::parse_from(["equirect2cubemap", "-", "-"])
+    };
+    let v = Vector3::new;
+    // the direction each side faces and the directions its image right and down point at
+    let expected = |side: Side| match side {
+        Side::Front => (v(1.0, 0.0, 0.0), v(0.0, 1.0, 0.0), v(0.0, 0.0, 1.0)),
+        Side::Back => (v(-1.0, 0.0, 0.0), v(0.0, -1.0, 0.0), v(0.0, 0.0, 1.0)),
+        Side::Left => (v(0.0, 1.0, 0.0), v(-1.0, 0.0, 0.0), v(0.0, 0.0, 1.0)),
+        Side::Right => (v(0.0, -1.0, 0.0), v(1.0, 0.0, 0.0), v(0.0, 0.0, 1.0)),
+        Side::Top => (v(0.0, 0.0, -1.0), v(1.0, 0.0, 0.0), v(0.0, -1.0, 0.0)),
+        Side::Bottom => (v(0.0, 0.0, 1.0), v(1.0, 0.0, 0.0), v(0.0, 1.0, 0.0)),
+    };
+    for (face, side) in convert_view(&config, &direction_panorama(1024)) {
+        let (facing, right, down) = expected(side);
+        let (mid, last) = (SIZE / 2, SIZE - 1);
+        let center =
+            (shown_direction(&face, mid - 1, mid) + shown_direction(&face, mid, mid)).normalize();
+        assert!(
+            angle_between(center, facing) < 0.05,
+            "{} {:?}",
+            side,
+            center
+        );
+        let to_right =
+            (shown_direction(&face, last, mid) - shown_direction(&face, 0, mid)).normalize();
+        let to_bottom =
+            (shown_direction(&face, mid, last) - shown_direction(&face, mid, 0)).normalize();
+        assert!(
+            angle_between(to_right, right) < 0.05,
+            "{} {:?}",
+            side,
+            to_right
+        );
+        assert!(
+            angle_between(to_bottom, down) < 0.05,
+            "{} {:?}",
+            side,
+            to_bottom
+        );
+        // seen from inside the cube the right and down axes cross to the facing direction,
+        // a mirrored face would cross to the opposite one
+        assert!(
+            angle_between(to_right.cross(&to_bottom), facing) < 0.1,
+            "{}",
+            side
+        );
+    }
+}
+
+#[test]
+fn tone_mapping_changes_8_bit_inputs() {
+    let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(4, 2, |x, y| {
+        Rgba([40 * x as u8 + 60 * y as u8, 200, 30, 128])
+    }));
+    let none = Config {
+        tone_map_operator: ToneMapping::None,
+        ..Config::parse_from(["equirect2cubemap", "-", "-"])
+    };
+    let aces = Config {
+        tone_map_operator: ToneMapping::Aces,
+        ..none.clone()
+    };
+    assert_eq!(tone_map(&none, img.clone()), img);
+    let mapped = tone_map(&aces, img.clone()).to_rgba8();
+    assert_eq!(mapped.dimensions(), (4, 2));
+    assert_ne!(mapped, img.to_rgba8());
+    // the curve lifts the mid-tones, crushes the shadows and keeps the alpha
+    for p in mapped.pixels() {
+        assert!(p.0[1] > 200, "{:?}", p);
+        assert!(p.0[2] < 30, "{:?}", p);
+        assert_eq!(p.0[3], 128);
+    }
+}
+
+#[test]
+fn ktx2_cubemap_layout() {
+    const SIZE: u32 = 8;
+    let color = |side: Side| {
+        let i = Side::all().iter().position(|s| *s == side).unwrap() as u8;
+        Rgba([i * 40, 255 - i * 40, i, 255])
+    };
+    let faces: Vec<_> = Side::all()
+        .into_iter()
+        .map(|side| (ImageBuffer::from_pixel(SIZE, SIZE, color(side)), side))
+        .collect();
+    let mut bytes = Vec::new();
+    write_ktx2_cubemap(&faces, ColorSpace::Srgb, &mut bytes).unwrap();
+    let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+    let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+    assert_eq!(
+        bytes[..12],
+        [0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n']
+    );
+    // format, width, height and face count
+    assert_eq!(u32_at(12), 43);
+    assert_eq!((u32_at(20), u32_at(24)), (SIZE, SIZE));
+    assert_eq!(u32_at(36), 6);
+    // the level index after the header, the level is all six faces uncompressed
+    let face_length = (SIZE * SIZE * 4) as usize;
+    assert_eq!(u64_at(80), KTX2_PREFIX_SIZE as u64);
+    assert_eq!(u64_at(88), 6 * face_length as u64);
+    assert_eq!(u64_at(96), 6 * face_length as u64);
+    let pixels = &bytes[KTX2_PREFIX_SIZE as usize..];
+    assert_eq!(pixels.len(), 6 * face_length);
+    for (face, side) in pixels.chunks(face_length).zip(KTX2_FACE_ORDER) {
+        assert!(face.chunks(4).all(|p| p == color(side).0), "{}", side);
+    }
+}
+
+#[test]
+fn conventions_follow_the_cube_map_lookup() {
+    const SIZE: u32 = 64;
+    let v = Vector3::new;
+    let (front, right, up) = (v(1.0, 0.0, 0.0), v(0.0, 1.0, 0.0), v(0.0, 0.0, -1.0));
+    let back = front.scale(-1.0);
+    // the world +x, +y and +z of each engine in the frame of the panorama
+    let worlds = [
+        // right handed y-up with the camera looking down -z
+        (Convention::Opengl, [right, up, back]),
+        // left handed y-up looking down +z
+        (Convention::Directx, [right, up, front]),
+        (Convention::Vulkan, [right, up, front]),
+        (Convention::Unity, [right, up, front]),
+        // left handed z-up, x forward
+        (Convention::Unreal, [front, right, up]),
+        // right handed z-up, the front view looks down +y
+        (Convention::Blender, [right, front, up]),
+    ];
+    // the major axis, s and t axes of the faces in `DDS_FACE_ORDER` (+x, -x, +y, -y, +z, -z)
+    // from the cube map lookup table, s runs along a row and t down the rows
+    let lookup: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+        ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ];
+    let panorama = direction_panorama(1024);
+    for (convention, [x, y, z]) in worlds {
+        let to_panorama = |[a, b, c]: [f32; 3]| x.scale(a) + y.scale(b) + z.scale(c);
+        let config = Config {
+            size: FaceSizes([SIZE; 6]),
+            convention: Some(convention),
+            ..Config::parse_from(["equirect2cubemap", "-", "-"])
+        };
+        let faces = orient_faces(&config, convert_view(&config, &panorama));
+        for (side, (major, s_axis, t_axis)) in DDS_FACE_ORDER.into_iter().zip(lookup) {
+            let (face, _) = faces.iter().find(|(_, s)| *s == side).unwrap();
+            let (major, s_axis, t_axis) =
+                (to_panorama(major), to_panorama(s_axis), to_panorama(t_axis));
+            for (px, py) in [
+                (0, 0),
+                (SIZE - 1, 0),
+                (10, 45),
+                (0, SIZE - 1),
+                (SIZE / 2, 20),
+            ] {
+                let s = (px as f32 + 0.5) / SIZE as f32 * 2.0 - 1.0;
+                let t = (py as f32 + 0.5) / SIZE as f32 * 2.0 - 1.0;
+                let expected = (major + s_axis.scale(s) + t_axis.scale(t)).normalize();
+                let shown = shown_direction(face, px, py);
+                // texels sample at their corner, so they're up to a texel off the lookup's
+                // texel centers after a flip
+                assert!(
+                    angle_between(shown, expected) < 1.5 / SIZE as f32,
+                    "{:?} {} ({}, {}): {:?} instead of {:?}",
+                    convention,
+                    side,
+                    px,
+                    py,
+                    shown,
+                    expected
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn a_line_on_the_seam_is_centered_on_the_back_face() {
+    const SIZE: u32 = 16;
+    // odd so the mip levels round down, the line is one texel on each side of u = 0
+    let width = 255;
+    let img: Rgba32FImage = ImageBuffer::from_fn(width, 128, |x, _| {
+        let c = if x == 0 || x == width - 1 { 1.0 } else { 0.0 };
+        Rgba([c, c, c, 1.0])
+    });
+    for interpolation in [Interpolation::Trilinear, Interpolation::Area] {
+        let config = Config {
+            size: FaceSizes([SIZE; 6]),
+            interpolation: interpolation.clone(),
+            ..Config::parse_from(["equirect2cubemap", "-", "-"])
+        };
+        let faces = convert_view(&config, &img);
+        let (back, _) = faces.iter().find(|(_, side)| *side == Side::Back).unwrap();
+        // texels sample at their corner, column SIZE / 2 is on the seam
+        let mid = SIZE / 2;
+        for y in 0..SIZE {
+            let at = |x: u32| back.get_pixel(x, y).0[0];
+            for k in 1..mid {
+                assert!(
+                    (at(mid - k) - at(mid + k)).abs() < 1e-3,
+                    "{:?} row {} at {} off the center: {} and {}",
+                    interpolation,
+                    y,
+                    k,
+                    at(mid - k),
+                    at(mid + k)
+                );
+            }
+            // a single line, brightest on the seam and fading away from it
+            assert!(
+                at(mid) > at(mid - 1) && at(mid - 1) >= at(mid - 2),
+                "{:?} row {}",
+                interpolation,
+                y
+            );
+            assert!(at(mid) > 0.01, "{:?} row {}", interpolation, y);
+        }
+    }
+}
+
+#[test]
+fn cubemap_methods_match_the_free_functions() {
+    const SIZE: u32 = 8;
+    // faces that look different under every turn and mirror
+    let entries = || -> Vec<(RgbaImage, Side)> {
+        Side::all()
+            .into_iter()
+            .enumerate()
+            .map(|(i, side)| {
+                let face = ImageBuffer::from_fn(SIZE, SIZE, |x, y| {
+                    Rgba([x as u8 * 30, y as u8 * 20 + x as u8, i as u8 * 40, 255])
+                });
+                (face, side)
+            })
+            .collect()
+    };
+    let cubemap = || Cubemap::from_entries(entries());
+    let orientations = FaceOrientations::from_str("front=90+flipx,top=180,bottom=270+flipy")
+        .unwrap()
+        .0;
+    assert_eq!(
+        cubemap().orient(&orientations).into_entries(),
+        orient(entries(), &orientations)
+    );
+    assert_eq!(cubemap().rotate_z_up().into_entries(), rotate(entries()));
+    for (flip_x, flip_y) in [(true, false), (false, true), (true, true)] {
+        let mut flipped = entries();
+        flip(&mut flipped, flip_x, flip_y);
+        assert_eq!(cubemap().flip(flip_x, flip_y).into_entries(), flipped);
+    }
+
+    // `apply_convention` moves texels of a converted cubemap while `--convention` samples the
+    // panorama again, so they're compared by the directions the texels show
+    const DIRECTIONS_SIZE: u32 = 32;
+    let panorama = direction_panorama(1024);
+    let plain = Config {
+        size: FaceSizes([DIRECTIONS_SIZE; 6]),
+        ..Config::parse_from(["equirect2cubemap", "-", "-"])
+    };
+    let converted = Cubemap::from_entries(convert_view(&plain, &panorama));
+    for convention in Convention::value_variants() {
+        let config = Config {
+            convention: Some(*convention),
+            ..plain.clone()
+        };
+        let expected =
+            Cubemap::from_entries(orient_faces(&config, convert_view(&config, &panorama)));
+        let moved = converted.clone().apply_convention(*convention);
+        for side in Side::all() {
+            for (a, b) in moved
+                .face(side)
+                .enumerate_pixels()
+                .zip(expected.face(side).enumerate_pixels())
+            {
+                let (shown, expected) = (
+                    shown_direction(moved.face(side), a.0, a.1),
+                    shown_direction(expected.face(side), b.0, b.1),
+                );
+                // texels sample at their corner in the conversion, so they're up to a texel
+                // apart on both axes, which is 2 / size radians at most
+                assert!(
+                    angle_between(shown, expected) < 3.0 / DIRECTIONS_SIZE as f32,
+                    "{:?} {} ({}, {})",
+                    convention,
+                    side,
+                    a.0,
+                    a.1
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn dithering_keeps_the_average_of_gradients() {
+    // a gradient two 8-bit steps wide, each 8x8 block of it is nearly flat
+    let (width, height) = (256, 8);
+    let exact = |x: u32| 100.0 + 2.0 * (x as f32 + 0.5) / width as f32;
+    let img: Rgba32FImage = ImageBuffer::from_fn(width, height, |x, _| {
+        let c = exact(x) / 255.0;
+        Rgba([c, c, c, 1.0])
+    });
+    let config = Config {
+        color_space: ColorSpace::Linear,
+        ..Config::parse_from(["equirect2cubemap", "-", "-"])
+    };
+    let dithered_config = Config {
+        dither: true,
+        ..config.clone()
+    };
+    let plain: RgbaImage = encode_color_space(&config, &img);
+    let dithered: RgbaImage = encode_color_space(&dithered_config, &img);
+    // how far the block means are off the gradient's, rounding makes bands of a single value
+    let banding = |quantized: &RgbaImage| -> f32 {
+        (0..width / 8)
+            .map(|block| {
+                let texels = (0..8).flat_map(|x| (0..8).map(move |y| (block * 8 + x, y)));
+                let error: f32 = texels
+                    .map(|(x, y)| quantized.get_pixel(x, y).0[0] as f32 - exact(x))
+                    .sum();
+                (error / 64.0).abs()
+            })
+            .sum::<f32>()
+            / (width / 8) as f32
+    };
+    assert!(banding(&plain) > 0.2, "{}", banding(&plain));
+    assert!(banding(&dithered) < 0.05, "{}", banding(&dithered));
+    // the variance of the 8x8 blocks, rounding alone leaves every block a single value
+    let variances = |quantized: &RgbaImage| -> Vec<f32> {
+        (0..width / 8)
+            .map(|block| {
+                let texels: Vec<f32> = (0..8)
+                    .flat_map(|x| (0..8).map(move |y| (block * 8 + x, y)))
+                    .map(|(x, y)| quantized.get_pixel(x, y).0[0] as f32)
+                    .collect();
+                let mean = texels.iter().sum::<f32>() / 64.0;
+                texels.iter().map(|c| (c - mean).powi(2)).sum::<f32>() / 64.0
+            })
+            .collect()
+    };
+    let (plain_variances, dithered_variances) = (variances(&plain), variances(&dithered));
+    assert!(
+        plain_variances.iter().all(|v| *v == 0.0),
+        "{:?}",
+        plain_variances
+    );
+    assert!(
+        dithered_variances.iter().all(|v| *v > 0.0),
+        "{:?}",
+        dithered_variances
+    );
+    // +-0.5 steps only reach the two values around the exact one
+    for (x, _, p) in dithered.enumerate_pixels() {
+        assert!((p.0[0] as f32 - exact(x)).abs() < 1.0, "{} {:?}", x, p);
+        assert_eq!(p.0[3], 255);
+    }
+}
+
+#[test]
+fn lanczos3_keeps_step_edges_in_bounds() {
+    let (low, high) = (0.25, 0.75);
+    // one step up at texel 16 and one down at the seam
+    let step: Rgba32FImage = ImageBuffer::from_fn(32, 8, |x, _| {
+        let c = if x < 16 { low } else { high };
+        Rgba([c, c, c, 1.0])
+    });
+    let at =
+        |interpolation: Interpolation, x: f32| interpolation.sample(&step, (x / 32.0, 0.5)).0[0];
+    // the overshoot of the windowed sinc peaks at 12% of the step between texel centers
+    let margin = (high - low) * 0.125;
+    for i in 0..=320 {
+        let x = i as f32 / 10.0;
+        let c = at(Interpolation::Lanczos3, x);
+        assert!(c > low - margin && c < high + margin, "{} at {}", c, x);
+        // there's no ringing past the 3 texels of the kernel
+        let to_edge = (x - 16.0).abs().min(x).min(32.0 - x);
+        if to_edge >= 3.5 {
+            let flat = if x < 16.0 { low } else { high };
+            assert!((c - flat).abs() < 1e-6, "{} at {}", c, x);
+        }
+    }
+
+    // just either side of the edge it's sharper than linear and within 2% of the step of bicubic,
+    // catmull-rom is a little steeper on a lone step but blurs the waves below
+    for d in [0.125, 0.25, 0.375] {
+        for (x, rail) in [(16.0 - d, low), (16.0 + d, high)] {
+            let (lanczos, bicubic, linear) = (
+                at(Interpolation::Lanczos3, x),
+                at(Interpolation::Bicubic, x),
+                at(Interpolation::Linear, x),
+            );
+            assert!(
+                (lanczos - rail).abs() < (linear - rail).abs(),
+                "{} and {} at {}",
+                lanczos,
+                linear,
+                x
+            );
+            assert!(
+                (lanczos - bicubic).abs() < (high - low) * 0.02,
+                "{} and {} at {}",
+                lanczos,
+                bicubic,
+                x
+            );
+        }
+    }
+
+    // between the texels of a wave 4 texels long it's closer to the wave than bicubic
+    let wave = |x: f32| 0.5 + 0.25 * (x * std::f32::consts::FRAC_PI_2).cos();
+    let waves: Rgba32FImage = ImageBuffer::from_fn(32, 8, |x, _| {
+        let c = wave(x as f32);
+        Rgba([c, c, c, 1.0])
+    });
+    let error = |interpolation: Interpolation| -> f32 {
+        // u = x / 32 is halfway between the centers of texels x - 1 and x
+        (1..=32)
+            .map(|x| x as f32)
+            .map(|x| (interpolation.sample(&waves, (x / 32.0, 0.5)).0[0] - wave(x - 0.5)).abs())
+            .sum()
+    };
+    let (lanczos, bicubic) = (
+        error(Interpolation::Lanczos3),
+        error(Interpolation::Bicubic),
+    );
+    assert!(lanczos < bicubic / 2.0, "{} and {}", lanczos, bicubic);
+
+    // the weights are normalized, so a constant image comes back as it is, poles included
+    let flat = RgbaImage::from_pixel(13, 7, Rgba([37, 150, 220, 255]));
+    for i in 0..=100 {
+        let uv = (i as f32 / 100.0, (i % 10) as f32 / 9.0);
+        assert_eq!(
+            Interpolation::Lanczos3.sample(&flat, uv),
+            Rgba([37, 150, 220, 255]),
+            "{:?}",
+            uv
+        );
+    }
+}
+
+#[test]
+fn invert_v_undoes_an_upside_down_panorama() {
+    const SIZE: u32 = 16;
+    let config = Config {
+        size: FaceSizes([SIZE; 6]),
+        ..Config::parse_from(["equirect2cubemap", "-", "-"])
+    };
+    let inverted = Config {
+        invert_v: true,
+        ..config.clone()
+    };
+    let panorama = direction_panorama(256);
+    let upside_down = image::imageops::flip_vertical(&panorama);
+    let expected = Cubemap::from_entries(convert_view(&config, &panorama));
+    let converted = Cubemap::from_entries(convert_view(&inverted, &upside_down));
+    for side in Side::all() {
+        for (x, y, p) in converted[side].enumerate_pixels() {
+            let q = expected[side].get_pixel(x, y);
+            assert!(
+                p.0.iter().zip(q.0).all(|(a, b)| a.abs_diff(b) <= 7),
+                "{} ({}, {}): {:?} instead of {:?}",
+                side,
+                x,
+                y,
+                p,
+                q
+            );
+        }
+    }
+    // without the flip, the flag swaps what the top and bottom show
+    let swapped = Cubemap::from_entries(convert_view(&inverted, &panorama));
+    let down = Vector3::new(0.0, 0.0, 1.0);
+    let mid = SIZE / 2;
+    assert!(angle_between(shown_direction(&expected[Side::Top], mid, mid), down) > 3.0);
+    assert!(angle_between(shown_direction(&swapped[Side::Top], mid, mid), down) < 0.1);
+    assert!(angle_between(shown_direction(&swapped[Side::Bottom], mid, mid), down) > 3.0);
+}
+
+#[test]
+fn premultiplied_alpha() {
+    let mut face = RgbaImage::from_fn(3, 1, |x, _| {
+        let alpha = [128, 0, 255][x as usize];
+        Rgba([200, 100, 50, alpha])
+    });
+    premultiply_alpha(&mut face);
+    assert_eq!(*face.get_pixel(0, 0), Rgba([100, 50, 25, 128]));
+    assert_eq!(*face.get_pixel(1, 0), Rgba([0, 0, 0, 0]));
+    assert_eq!(*face.get_pixel(2, 0), Rgba([200, 100, 50, 255]));
+    // alpha is a fraction of the channel range at every depth
+    let mut face: FaceBuffer<Rgba<f32>> = ImageBuffer::from_pixel(1, 1, Rgba([0.8, 0.4, 2.0, 0.5]));
+    premultiply_alpha(&mut face);
+    assert_eq!(*face.get_pixel(0, 0), Rgba([0.4, 0.2, 1.0, 0.5]));
+}
+
+#[test]
+fn scalar_faces_of_one_channel_float_panoramas() {
+    // a depth panorama growing from the top row down, far outside 0 to 1
+    let panorama = ScalarImage::from_fn(32, 16, |_, y| Luma([1000.0 + y as f32]));
+    for format in ["exr", "tiff"] {
+        let config = Config::parse_from([
+            "equirect2cubemap",
+            "--scalar",
+            "-f",
+            format,
+            "-s",
+            "8",
+            "-",
+            "-",
+        ]);
+        let mut input = Cursor::new(Vec::new());
+        write_scalar_image(&panorama, &mut input, config.format.of(Side::Front)).unwrap();
+        let faces = convert_bytes(input.get_ref(), &config).unwrap();
+        assert_eq!(faces.len(), 6);
+        // the saved faces are read back the same way
+        let faces: Vec<_> = faces
+            .into_iter()
+            .map(|(bytes, side)| {
+                let reader = image::io::Reader::new(Cursor::new(bytes));
+                let (img, _) = decode_input(&config, reader).unwrap();
+                (to_scalar(&to_samplable(img)), side)
+            })
+            .collect();
+        let face = |side: Side| &faces.iter().find(|(_, s)| *s == side).unwrap().0;
+        for (face, side) in &faces {
+            assert_eq!(face.dimensions(), (8, 8));
+            assert!(
+                face.pixels().all(|p| (1000.0..=1015.0).contains(&p.0[0])),
+                "{} {}",
+                format,
+                side
+            );
+        }
+        // the top face shows the rows above the bottom face's
+        let top_max = face(Side::Top)
+            .pixels()
+            .map(|p| p.0[0])
+            .fold(f32::MIN, f32::max);
+        let bottom_min = face(Side::Bottom)
+            .pixels()
+            .map(|p| p.0[0])
+            .fold(f32::MAX, f32::min);
+        assert!(
+            top_max < 1005.0 && bottom_min > 1010.0,
+            "{} {} {}",
+            format,
+            top_max,
+            bottom_min
+        );
+        // texels sample at their corner, row 4 of the front face is on the horizon
+        let horizon = face(Side::Front).get_pixel(4, 4).0[0];
+        assert!((horizon - 1007.5).abs() < 0.01, "{} {}", format, horizon);
+    }
+}
+
+#[test]
+fn config_file_numbers_may_have_underscores() {
+    use config_file::{parse_toml, Value};
+    let entries = parse_toml("size = 1_024\nexposure = 1_0.2_5").unwrap();
+    assert_eq!(
+        entries,
+        [
+            ("size".to_owned(), Value::Number("1024".to_owned())),
+            ("exposure".to_owned(), Value::Number("10.25".to_owned())),
+        ]
+    );
+    for bad in ["_1", "1_", "1__0", "1_.5"] {
+        assert!(parse_toml(&format!("size = {}", bad)).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn batch_samples_match_single_samples() {
+    // noise, so a texel read or weighted differently shows
+    let noise = |x: u32, y: u32, c: u32| ((x * 7919 + y * 104_729 + c * 31) % 997) as f32 / 997.0;
+    let float: Rgba32FImage = ImageBuffer::from_fn(37, 19, |x, y| {
+        Rgba([
+            3.0 * noise(x, y, 0),
+            noise(x, y, 1),
+            0.01 * noise(x, y, 2),
+            noise(x, y, 3),
+        ])
+    });
+    let bytes = DynamicImage::ImageRgba32F(float.clone()).to_rgba8();
+    let mut uvs = Vec::new();
+    for i in 0..=40 {
+        let t = i as f32 / 40.0;
+        // across the image, on both sides of the seam and at the poles
+        uvs.extend([(t, t * t), (1.0 - 0.01 * t, 0.5), (0.01 * t, 0.5)]);
+        uvs.extend([(t, 0.0), (t, 1.0), (t, 0.001), (t, 0.999)]);
+    }
+    // v past the poles or NaN fails, u past the edges is addressed
+    uvs.extend([(0.5, -0.01), (0.5, 1.01), (0.5, f32::NAN), (f32::NAN, 0.5)]);
+    uvs.extend([(-0.2, 0.3), (1.3, 0.7), (-1.0, 0.5), (2.0, 0.5)]);
+    // the channels of each sample as bits, so NaNs compare too
+    type Bits = Vec<Option<[u32; 4]>>;
+    fn batch_and_single<P: SamplePixel>(
+        img: &FaceBuffer<P>,
+        uvs: &[(f32, f32)],
+        addressing: Addressing,
+    ) -> (Bits, Bits) {
+        let bits = |p: Option<P>| p.map(|p| p.to_f32().map(f32::to_bits));
+        let mut batch = vec![None; uvs.len()];
+        Interpolation::Linear.try_sample_batch(img, uvs, addressing, &mut batch);
+        let single = uvs
+            .iter()
+            .map(|uv| bits(Interpolation::Linear.try_sample_addressed(img, *uv, addressing)))
+            .collect();
+        (batch.into_iter().map(bits).collect(), single)
+    }
+    for addressing in [Addressing::PANORAMA, Addressing::CLAMPED] {
+        // batches of four and the remainders of one to three uvs after them
+        for len in uvs.len() - 3..=uvs.len() {
+            for (batch, single) in [
+                batch_and_single(&float, &uvs[..len], addressing),
+                batch_and_single(&bytes, &uvs[..len], addressing),
+            ] {
+                for ((batch, single), uv) in batch.iter().zip(&single).zip(&uvs) {
+                    assert_eq!(batch, single, "{:?} {:?}", addressing, uv);
+                }
+            }
+        }
+        // fewer uvs than a batch
+        for len in 1..4 {
+            let (batch, single) = batch_and_single(&float, &uvs[..len], addressing);
+            assert_eq!(batch, single);
+        }
+    }
+}